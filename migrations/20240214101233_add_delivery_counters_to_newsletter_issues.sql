@@ -0,0 +1,3 @@
+ALTER TABLE newsletter_issues ADD COLUMN n_total INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE newsletter_issues ADD COLUMN n_sent INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE newsletter_issues ADD COLUMN n_failed INTEGER NOT NULL DEFAULT 0;
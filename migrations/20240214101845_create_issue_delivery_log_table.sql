@@ -0,0 +1,9 @@
+CREATE TABLE issue_delivery_log (
+    id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    detail TEXT NULL,
+    logged_at timestamptz NOT NULL
+);
+CREATE INDEX issue_delivery_log_issue_idx ON issue_delivery_log (newsletter_issue_id);
@@ -0,0 +1 @@
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'sending';
@@ -0,0 +1,5 @@
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '24 hours',
+    ADD COLUMN consumed_at timestamptz NULL;
+CREATE INDEX subscription_tokens_expires_at_idx ON subscription_tokens (expires_at);
@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
+-- Set on tokens confirming a change of address rather than a subscription.
+ALTER TABLE subscription_tokens ADD COLUMN new_email TEXT NULL;
@@ -0,0 +1,26 @@
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id)
+);
+CREATE TABLE list_subscriptions (
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX list_subscriptions_subscriber_id_idx ON list_subscriptions (subscriber_id);
+
+-- Everything so far was sent to a single implicit list.
+INSERT INTO lists (list_id, slug, name)
+VALUES ('0b4bc7d4-6f0b-4c8e-9a53-6d1f0f0c5a11', 'newsletter', 'Newsletter');
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT '0b4bc7d4-6f0b-4c8e-9a53-6d1f0f0c5a11', id, status, subscribed_at
+FROM subscriptions;
+
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE newsletter_issues SET list_id = '0b4bc7d4-6f0b-4c8e-9a53-6d1f0f0c5a11';
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
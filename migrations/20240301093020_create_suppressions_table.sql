@@ -0,0 +1,18 @@
+-- Addresses that must not be added back. Only a hash of the normalised
+-- address is kept, so that erased subscribers leave no readable trace.
+CREATE TABLE suppressions (
+    email_hash TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (email_hash)
+);
+
+-- Deleting a subscriber takes everything that hangs off them along.
+ALTER TABLE subscription_tokens
+    DROP CONSTRAINT subscription_tokens_subscriber_id_fkey,
+    ADD CONSTRAINT subscription_tokens_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+ALTER TABLE list_subscriptions
+    DROP CONSTRAINT list_subscriptions_subscriber_id_fkey,
+    ADD CONSTRAINT list_subscriptions_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
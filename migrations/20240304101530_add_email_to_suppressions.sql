@@ -0,0 +1,10 @@
+-- Kept next to the hash so that admins can see who is suppressed. Left
+-- empty for erased subscribers.
+ALTER TABLE suppressions ADD COLUMN email TEXT NULL;
+CREATE INDEX suppressions_created_at_idx ON suppressions (created_at);
+
+-- Must agree with `suppression::email_hash`, so that queries can match
+-- subscribers against the suppression list.
+CREATE FUNCTION hash_email(email TEXT) RETURNS TEXT
+    LANGUAGE SQL IMMUTABLE STRICT
+    AS $$ SELECT encode(sha256(convert_to(lower(btrim(email)), 'UTF8')), 'hex') $$;
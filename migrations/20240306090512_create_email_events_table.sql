@@ -0,0 +1,18 @@
+-- Delivery, bounce and complaint notifications posted by the email provider.
+CREATE TABLE email_events (
+    event_id uuid NOT NULL,
+    kind TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    newsletter_issue_id uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE SET NULL,
+    -- The provider's id for the message, used to ignore redelivered events.
+    provider_message_id TEXT NULL,
+    detail TEXT NULL,
+    payload jsonb NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (event_id),
+    UNIQUE (provider_message_id, kind)
+);
+CREATE INDEX email_events_issue_idx ON email_events (newsletter_issue_id);
+CREATE INDEX email_events_recipient_idx ON email_events (lower(recipient));
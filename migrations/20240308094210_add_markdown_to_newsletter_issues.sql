@@ -0,0 +1,3 @@
+-- The source of issues authored in Markdown, from which both bodies were
+-- generated. NULL for issues written as HTML and plain text.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
@@ -0,0 +1,2 @@
+-- Where test sends of draft issues go. NULL until the admin sets it.
+ALTER TABLE users ADD COLUMN email TEXT NULL;
@@ -0,0 +1,15 @@
+-- HTML wrapped around the body of issues, showing it where it refers to
+-- {{ content }}.
+CREATE TABLE layouts (
+    layout_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    html TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+-- At most one layout is used by default.
+CREATE UNIQUE INDEX layouts_is_default_idx ON layouts (is_default) WHERE is_default;
+
+-- NULL uses the default layout, if there is one.
+ALTER TABLE newsletter_issues
+    ADD COLUMN layout_id uuid NULL REFERENCES layouts (layout_id) ON DELETE SET NULL;
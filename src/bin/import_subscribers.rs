@@ -0,0 +1,88 @@
+//! Imports subscribers from a CSV file with `email` and `name` columns, for
+//! files too large to upload comfortably:
+//!
+//!     cargo run --bin import_subscribers -- subscribers.csv [--list SLUG] [--pre-confirmed]
+//!
+//! Prints one tab-separated line per row: line, outcome, email and reason.
+//! Confirmation emails are sent by the background worker.
+use anyhow::Context;
+use email_newsletter::{
+    config,
+    domain::ListSlug,
+    subscriber_import::{ImportOptions, RowOutcome, SubscriberImport},
+    telemetry::{get_subscriber, init_subscriber},
+};
+use sqlx::postgres::PgPoolOptions;
+use tokio::io::AsyncReadExt;
+
+const USAGE: &str = "Usage: import_subscribers FILE [--list SLUG] [--pre-confirmed]";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let subscriber = get_subscriber("import_subscribers".into(), "info".into(), std::io::stderr);
+    init_subscriber(subscriber);
+
+    let mut path = None;
+    let mut list = "newsletter".to_owned();
+    let mut pre_confirmed = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" => list = args.next().context(USAGE)?,
+            "--pre-confirmed" => pre_confirmed = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+    let path = path.context(USAGE)?;
+    let list = ListSlug::parse(list).map_err(anyhow::Error::msg)?;
+
+    let configuration = config::get_configuration().expect("Failed to read configuration.");
+    let pool = PgPoolOptions::new().connect_lazy_with(configuration.database.get_db_options());
+    let list_id = sqlx::query_scalar!("SELECT list_id FROM lists WHERE slug = $1", list.as_ref())
+        .fetch_optional(&pool)
+        .await
+        .context("Failed to look up the mailing list.")?
+        .with_context(|| format!("There is no {} list.", list.as_ref()))?;
+
+    let mut file = tokio::fs::File::open(&path)
+        .await
+        .with_context(|| format!("Failed to open {path}"))?;
+    let options = ImportOptions {
+        list_id,
+        pre_confirmed,
+    };
+    let mut import = SubscriberImport::new(&pool, options);
+    let mut buffer = vec![0; 64 * 1024];
+    let result = async {
+        loop {
+            let n = file
+                .read(&mut buffer)
+                .await
+                .with_context(|| format!("Failed to read {path}"))?;
+            if n == 0 {
+                break;
+            }
+            import.push(&buffer[..n]).await?;
+        }
+        import.finish().await.map_err(anyhow::Error::from)
+    }
+    .await;
+    // Rows before an interruption are committed, so they are reported too.
+    let report = import.into_report();
+
+    for row in &report {
+        println!(
+            "{}\t{:?}\t{}\t{}",
+            row.line, row.outcome, row.email, row.reason
+        );
+    }
+    let count = |outcome| report.iter().filter(|row| row.outcome == outcome).count();
+    eprintln!(
+        "{} accepted, {} skipped, {} rejected.",
+        count(RowOutcome::Accepted),
+        count(RowOutcome::Skipped),
+        count(RowOutcome::Rejected)
+    );
+    result
+}
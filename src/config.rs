@@ -1,24 +1,79 @@
 use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailClient, EmailTransport, OutboxTransport, PostmarkTransport, SmtpTls, SmtpTransport,
+};
 use secrecy::{ExposeSecret, Secret};
 use serde_yaml;
 use serde_yaml::Error as YamlError;
 use sqlx::postgres::PgConnectOptions;
 use std::io::Error as IoError;
+use std::sync::Arc;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub app_settings: AppSettings,
     pub email_client: EmailClientSettings,
+    #[serde(default)]
+    pub worker: WorkerSettings,
+    /// Without it, provider events are refused.
+    pub webhooks: Option<WebhookSettings>,
     pub redis_uri: Secret<String>,
 }
 
+/// Basic-auth credentials the email provider uses to post events, set in
+/// the provider's webhook URL.
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct WorkerSettings {
+    pub n_workers: usize,
+    pub batch_size: usize,
+    pub empty_queue_poll_ms: u64,
+    pub error_backoff_ms: u64,
+}
+
+impl Default for WorkerSettings {
+    fn default() -> Self {
+        Self {
+            n_workers: 1,
+            batch_size: 100,
+            empty_queue_poll_ms: 15_000,
+            error_backoff_ms: 1_000,
+        }
+    }
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
-    pub base_url: String,
     pub sender_email: String,
-    pub authorization_token: Secret<String>,
     pub timeout_ms: u64,
+    #[serde(flatten)]
+    pub transport: EmailTransportSettings,
+}
+
+/// Selected through the `transport` key of the `email_client` section.
+#[derive(serde::Deserialize, Clone)]
+#[serde(tag = "transport", rename_all = "lowercase")]
+pub enum EmailTransportSettings {
+    Postmark {
+        base_url: String,
+        authorization_token: Secret<String>,
+    },
+    Smtp {
+        host: String,
+        port: u16,
+        #[serde(default)]
+        tls: SmtpTls,
+        username: Option<String>,
+        password: Option<Secret<String>>,
+    },
+    /// Writes messages to a local directory, handy for development.
+    Outbox { directory: String },
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -36,6 +91,8 @@ pub struct AppSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Shown in the footer of every issue, as anti-spam laws require.
+    pub postal_address: String,
 }
 
 #[derive(Debug)]
@@ -64,6 +121,45 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_ms)
     }
+
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let transport: Arc<dyn EmailTransport> = match self.transport {
+            EmailTransportSettings::Postmark {
+                base_url,
+                authorization_token,
+            } => Arc::new(PostmarkTransport::new(
+                base_url,
+                authorization_token,
+                timeout,
+            )),
+            EmailTransportSettings::Smtp {
+                host,
+                port,
+                tls,
+                username,
+                password,
+            } => Arc::new(
+                SmtpTransport::new(&host, port, tls, username.zip(password), timeout)
+                    .expect("Invalid SMTP relay settings."),
+            ),
+            EmailTransportSettings::Outbox { directory } => Arc::new(
+                OutboxTransport::new(directory).expect("Failed to create the outbox directory."),
+            ),
+        };
+        EmailClient::new(sender_email, transport)
+    }
+}
+
+impl WorkerSettings {
+    pub fn empty_queue_poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.empty_queue_poll_ms)
+    }
+
+    pub fn error_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.error_backoff_ms)
+    }
 }
 
 impl DatabaseSettings {
@@ -0,0 +1,150 @@
+//! Confirmation emails sent in the background, for imports that create
+//! far too many subscribers to email while the file is being uploaded.
+use crate::{
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    email_client::EmailClient,
+    issue_delivery_worker::{backoff, PgTransaction, MAX_RETRIES},
+    routes::send_confirmation_email,
+};
+use chrono::Utc;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::watch;
+
+const EMPTY_QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+struct QueuedEmail {
+    subscription_token: String,
+    n_retries: i16,
+    email: String,
+    name: String,
+}
+
+enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut PgTransaction,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscription_token)
+        VALUES ($1)
+        "#,
+        subscription_token
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Sends queued confirmation emails, one at a time.
+pub async fn confirmation_queue_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    error_backoff: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        if *shutdown.borrow() || shutdown.has_changed().is_err() {
+            return Ok(());
+        }
+        let pause = match try_send_confirmation(&pool, &email_client, &base_url).await {
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+            Ok(ExecutionOutcome::EmptyQueue) => EMPTY_QUEUE_POLL_INTERVAL,
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to process the confirmation email queue",
+                );
+                error_backoff
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.changed() => {}
+        }
+    }
+}
+
+/// Failed emails are retried with a backoff, then dropped: the subscriber
+/// can still ask for another one from the subscribe form.
+#[tracing::instrument(skip_all)]
+async fn try_send_confirmation(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let queued = sqlx::query_as!(
+        QueuedEmail,
+        r#"
+        SELECT q.subscription_token, q.n_retries, s.email, s.name
+        FROM confirmation_email_queue q
+        JOIN subscription_tokens t ON t.subscription_token = q.subscription_token
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(queued) = queued else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let new_subscriber = SubscriberEmail::parse_email(queued.email).and_then(|email| {
+        let name = SubscriberName::parse_name(queued.name)?;
+        Ok(NewSubscriber { email, name })
+    });
+    let result = match new_subscriber {
+        Ok(new_subscriber) => {
+            send_confirmation_email(
+                pool,
+                email_client,
+                &new_subscriber,
+                base_url,
+                &queued.subscription_token,
+            )
+            .await
+        }
+        Err(e) => Err(anyhow::anyhow!(e)),
+    };
+    match result {
+        Err(e) if queued.n_retries < MAX_RETRIES => {
+            tracing::warn!(error.cause_chain = ?e, "Failed to send a confirmation email, retrying later");
+            let execute_after = Utc::now() + chrono::Duration::from_std(backoff(queued.n_retries))?;
+            sqlx::query!(
+                r#"
+                UPDATE confirmation_email_queue
+                SET n_retries = n_retries + 1, execute_after = $2
+                WHERE subscription_token = $1
+                "#,
+                queued.subscription_token,
+                execute_after
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+        result => {
+            if let Err(e) = result {
+                tracing::error!(error.cause_chain = ?e, "Giving up on a confirmation email");
+            }
+            sqlx::query!(
+                r#"DELETE FROM confirmation_email_queue WHERE subscription_token = $1"#,
+                queued.subscription_token
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
@@ -0,0 +1,52 @@
+/// The public identifier of a mailing list, as used by the subscribe form.
+#[derive(Debug, Clone)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_valid = !s.is_empty()
+            && s.len() <= 64
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!(
+                "{} is not a valid list identifier. Use lowercase letters, digits and dashes.",
+                s
+            ))
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_slugs_with_dashes_are_accepted() {
+        assert_ok!(ListSlug::parse("weekly-digest-2".into()));
+    }
+
+    #[test]
+    fn empty_slug_is_rejected() {
+        assert_err!(ListSlug::parse("".into()));
+    }
+
+    #[test]
+    fn uppercase_and_spaces_are_rejected() {
+        assert_err!(ListSlug::parse("Weekly digest".into()));
+    }
+
+    #[test]
+    fn overly_long_slug_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+}
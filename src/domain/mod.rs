@@ -1,7 +1,15 @@
+mod list_slug;
 mod new_subscriber;
+mod scoped_token;
+mod segment_filter;
 mod subscriber_email;
 mod subscriber_name;
+mod unsubscribe_token;
 
+pub use list_slug::ListSlug;
 pub use new_subscriber::NewSubscriber;
+pub use scoped_token::{ScopedToken, TokenPurpose};
+pub use segment_filter::{parse_identifier, SegmentFilter};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use unsubscribe_token::UnsubscribeToken;
@@ -0,0 +1,139 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// What a `ScopedToken` allows. Each purpose is signed separately, so that
+/// a token issued for one can't be used for another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenPurpose {
+    DataAccess,
+    EmailChange,
+}
+
+impl TokenPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::DataAccess => "data-access",
+            TokenPurpose::EmailChange => "email-change",
+        }
+    }
+}
+
+/// An HMAC tag allowing a sensitive action on a subscriber until it expires.
+/// Unlike `UnsubscribeToken`, it is never embedded in issues: it is only
+/// emailed to the subscriber when they ask for it.
+#[derive(Debug, Clone)]
+pub struct ScopedToken(String);
+
+impl ScopedToken {
+    pub fn issue(
+        purpose: TokenPurpose,
+        subscriber_id: Uuid,
+        expires_at: DateTime<Utc>,
+        secret: &Secret<String>,
+    ) -> Self {
+        let expires_at = expires_at.timestamp();
+        let tag = mac(purpose, subscriber_id, expires_at, secret)
+            .finalize()
+            .into_bytes();
+        Self(format!("{expires_at}.{}", hex::encode(tag)))
+    }
+
+    pub fn verify(
+        purpose: TokenPurpose,
+        subscriber_id: Uuid,
+        token: &str,
+        secret: &Secret<String>,
+    ) -> Result<(), anyhow::Error> {
+        let (expires_at, tag) = token.split_once('.').context("Malformed token.")?;
+        let expires_at: i64 = expires_at.parse()?;
+        let tag = hex::decode(tag)?;
+        mac(purpose, subscriber_id, expires_at, secret).verify_slice(&tag)?;
+        if expires_at <= Utc::now().timestamp() {
+            anyhow::bail!("The token has expired.");
+        }
+        Ok(())
+    }
+}
+
+fn mac(
+    purpose: TokenPurpose,
+    subscriber_id: Uuid,
+    expires_at: i64,
+    secret: &Secret<String>,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+    mac.update(purpose.as_str().as_bytes());
+    mac.update(b":");
+    mac.update(subscriber_id.as_bytes());
+    mac.update(&expires_at.to_be_bytes());
+    mac
+}
+
+impl AsRef<str> for ScopedToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScopedToken, TokenPurpose};
+    use crate::domain::UnsubscribeToken;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key".into())
+    }
+
+    #[test]
+    fn issued_tokens_are_accepted_until_they_expire() {
+        let id = Uuid::new_v4();
+        let purpose = TokenPurpose::DataAccess;
+        let token = ScopedToken::issue(purpose, id, Utc::now() + Duration::hours(1), &secret());
+        assert_ok!(ScopedToken::verify(purpose, id, token.as_ref(), &secret()));
+        let token = ScopedToken::issue(purpose, id, Utc::now() - Duration::hours(1), &secret());
+        assert_err!(ScopedToken::verify(purpose, id, token.as_ref(), &secret()));
+    }
+
+    #[test]
+    fn the_expiry_cannot_be_extended() {
+        let id = Uuid::new_v4();
+        let purpose = TokenPurpose::DataAccess;
+        let token = ScopedToken::issue(purpose, id, Utc::now() - Duration::hours(1), &secret());
+        let (_, tag) = token.as_ref().split_once('.').unwrap();
+        let forged = format!("{}.{tag}", (Utc::now() + Duration::hours(1)).timestamp());
+        assert_err!(ScopedToken::verify(purpose, id, &forged, &secret()));
+    }
+
+    #[test]
+    fn tokens_are_bound_to_their_purpose() {
+        let id = Uuid::new_v4();
+        let expires_at = Utc::now() + Duration::hours(1);
+        let token = ScopedToken::issue(TokenPurpose::EmailChange, id, expires_at, &secret());
+        assert_err!(ScopedToken::verify(
+            TokenPurpose::DataAccess,
+            id,
+            token.as_ref(),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn unsubscribe_tokens_are_rejected() {
+        let id = Uuid::new_v4();
+        let token = UnsubscribeToken::for_subscriber(id, &secret());
+        assert_err!(ScopedToken::verify(
+            TokenPurpose::DataAccess,
+            id,
+            token.as_ref(),
+            &secret()
+        ));
+    }
+}
@@ -0,0 +1,225 @@
+use chrono::{NaiveDate, NaiveTime};
+use sqlx::{Postgres, QueryBuilder};
+
+/// A saved segment's filter: conditions on a subscriber that must all hold,
+/// written e.g. as `tag:vip and not attr:region="North America"`.
+///
+/// Supported conditions are `tag:NAME`, `attr:KEY=VALUE`,
+/// `subscribed_before:YYYY-MM-DD` and `subscribed_after:YYYY-MM-DD`, each
+/// optionally preceded by `not`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentFilter(Vec<Term>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    HasTag(String),
+    AttributeEquals(String, String),
+    SubscribedBefore(NaiveDate),
+    SubscribedAfter(NaiveDate),
+}
+
+impl SegmentFilter {
+    pub fn parse(s: &str) -> Result<SegmentFilter, String> {
+        let tokens = tokenize(s)?;
+        let mut terms = vec![];
+        let mut tokens = tokens.into_iter().peekable();
+        loop {
+            let negated = tokens.next_if(|t| t.eq_ignore_ascii_case("not")).is_some();
+            let condition = match tokens.next() {
+                Some(token) => parse_condition(&token)?,
+                None => return Err("The filter is incomplete.".into()),
+            };
+            terms.push(Term { negated, condition });
+            match tokens.next() {
+                None => break,
+                Some(token) if token.eq_ignore_ascii_case("and") => {}
+                Some(token) => return Err(format!("Expected `and`, found `{token}`.")),
+            }
+        }
+        Ok(Self(terms))
+    }
+
+    /// Appends one `AND ...` clause per condition. Subscriber columns are
+    /// expected to be available under the `s` alias.
+    pub fn push_conditions(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        for term in &self.0 {
+            query.push(if term.negated {
+                " AND NOT COALESCE(("
+            } else {
+                " AND COALESCE(("
+            });
+            match &term.condition {
+                Condition::HasTag(tag) => {
+                    query.push_bind(tag.clone()).push(" = ANY(s.tags)");
+                }
+                Condition::AttributeEquals(key, value) => {
+                    query
+                        .push("s.attributes ->> ")
+                        .push_bind(key.clone())
+                        .push(" = ")
+                        .push_bind(value.clone());
+                }
+                Condition::SubscribedBefore(date) => {
+                    query
+                        .push("s.subscribed_at < ")
+                        .push_bind(date.and_time(NaiveTime::MIN).and_utc());
+                }
+                Condition::SubscribedAfter(date) => {
+                    query
+                        .push("s.subscribed_at >= ")
+                        .push_bind(date.and_time(NaiveTime::MIN).and_utc());
+                }
+            }
+            query.push("), false)");
+        }
+    }
+}
+
+/// Tags and attribute keys: lowercase letters, digits, `-` and `_`.
+pub fn parse_identifier(s: &str) -> Result<String, String> {
+    let s = s.trim().to_lowercase();
+    let is_valid = !s.is_empty()
+        && s.len() <= 64
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_valid {
+        Ok(s)
+    } else {
+        Err(format!(
+            "{s} is not a valid tag or attribute name. Use letters, digits, dashes and underscores."
+        ))
+    }
+}
+
+fn parse_condition(token: &str) -> Result<Condition, String> {
+    let (kind, argument) = token
+        .split_once(':')
+        .ok_or_else(|| format!("`{token}` is not a condition."))?;
+    let parse_date = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("{date} is not a date."))
+    };
+    match kind.to_lowercase().as_str() {
+        "tag" => Ok(Condition::HasTag(parse_identifier(argument)?)),
+        "attr" => {
+            let (key, value) = argument
+                .split_once('=')
+                .ok_or_else(|| format!("`{token}` should look like attr:KEY=VALUE."))?;
+            Ok(Condition::AttributeEquals(
+                parse_identifier(key)?,
+                value.to_owned(),
+            ))
+        }
+        "subscribed_before" => Ok(Condition::SubscribedBefore(parse_date(argument)?)),
+        "subscribed_after" => Ok(Condition::SubscribedAfter(parse_date(argument)?)),
+        _ => Err(format!("Unknown condition `{kind}`.")),
+    }
+}
+
+/// Splits on whitespace, except within double quotes (which are dropped).
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in s.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("Unterminated quote.".into());
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Condition, SegmentFilter, Term};
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok};
+    use sqlx::QueryBuilder;
+
+    #[test]
+    fn a_single_tag_is_parsed() {
+        let filter = assert_ok!(SegmentFilter::parse("tag:vip"));
+        assert_eq!(
+            filter,
+            SegmentFilter(vec![Term {
+                negated: false,
+                condition: Condition::HasTag("vip".into())
+            }])
+        );
+    }
+
+    #[test]
+    fn conditions_can_be_combined_and_negated() {
+        let filter = assert_ok!(SegmentFilter::parse(
+            r#"attr:region="North America" AND not subscribed_before:2024-01-01"#
+        ));
+        assert_eq!(
+            filter,
+            SegmentFilter(vec![
+                Term {
+                    negated: false,
+                    condition: Condition::AttributeEquals("region".into(), "North America".into())
+                },
+                Term {
+                    negated: true,
+                    condition: Condition::SubscribedBefore(
+                        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+                    )
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn empty_filter_is_rejected() {
+        assert_err!(SegmentFilter::parse("  "));
+    }
+
+    #[test]
+    fn dangling_and_is_rejected() {
+        assert_err!(SegmentFilter::parse("tag:vip and"));
+    }
+
+    #[test]
+    fn missing_and_is_rejected() {
+        assert_err!(SegmentFilter::parse("tag:vip tag:beta"));
+    }
+
+    #[test]
+    fn unknown_conditions_are_rejected() {
+        assert_err!(SegmentFilter::parse("country:fr"));
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        assert_err!(SegmentFilter::parse("subscribed_after:yesterday"));
+    }
+
+    #[test]
+    fn values_are_bound_rather_than_inlined() {
+        let filter = SegmentFilter::parse(r#"not attr:name="'; DROP TABLE x; --""#).unwrap();
+        let mut query = QueryBuilder::new("SELECT 1 FROM subscriptions s WHERE true");
+        filter.push_conditions(&mut query);
+        assert_eq!(
+            query.sql(),
+            "SELECT 1 FROM subscriptions s WHERE true AND NOT COALESCE((s.attributes ->> $1 = $2), false)"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// An HMAC tag proving that an unsubscribe link was issued by us for a given
+/// subscriber. It is derived from the subscriber id, so it never has to be
+/// stored and stays valid for as long as the secret doesn't change.
+#[derive(Debug, Clone)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn for_subscriber(subscriber_id: Uuid, secret: &Secret<String>) -> Self {
+        let tag = mac(subscriber_id, secret).finalize().into_bytes();
+        Self(hex::encode(tag))
+    }
+
+    pub fn verify(
+        subscriber_id: Uuid,
+        token: &str,
+        secret: &Secret<String>,
+    ) -> Result<(), anyhow::Error> {
+        let tag = hex::decode(token)?;
+        mac(subscriber_id, secret).verify_slice(&tag)?;
+        Ok(())
+    }
+}
+
+fn mac(subscriber_id: Uuid, secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+    mac.update(b"unsubscribe:");
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key".into())
+    }
+
+    #[test]
+    fn issued_tokens_are_accepted() {
+        let id = Uuid::new_v4();
+        let token = UnsubscribeToken::for_subscriber(id, &secret());
+        assert_ok!(UnsubscribeToken::verify(id, token.as_ref(), &secret()));
+    }
+
+    #[test]
+    fn tokens_are_bound_to_their_subscriber() {
+        let token = UnsubscribeToken::for_subscriber(Uuid::new_v4(), &secret());
+        assert_err!(UnsubscribeToken::verify(
+            Uuid::new_v4(),
+            token.as_ref(),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let id = Uuid::new_v4();
+        let token = UnsubscribeToken::for_subscriber(id, &Secret::new("another-key".into()));
+        assert_err!(UnsubscribeToken::verify(id, token.as_ref(), &secret()));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(UnsubscribeToken::verify(
+            Uuid::new_v4(),
+            "not-hex",
+            &secret()
+        ));
+    }
+}
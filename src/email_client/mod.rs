@@ -0,0 +1,148 @@
+mod outbox;
+mod postmark;
+mod smtp;
+
+pub use outbox::{OutboxRecord, OutboxTransport};
+pub use postmark::PostmarkTransport;
+pub use smtp::{SmtpTls, SmtpTransport};
+
+use crate::domain::SubscriberEmail;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// The most messages a single `send_batch` call may be given.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: SubscriberEmail,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    /// One-click unsubscribe endpoint for this recipient, if any.
+    pub unsubscribe_url: Option<String>,
+    /// Lets bounce and complaint events be traced back to the issue.
+    pub newsletter_issue_id: Option<Uuid>,
+}
+
+impl EmailMessage {
+    /// Extra headers every transport has to set on the message.
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![];
+        if let Some(url) = &self.unsubscribe_url {
+            // RFC 8058 one-click unsubscribe.
+            headers.push(("List-Unsubscribe", format!("<{url}>")));
+            headers.push(("List-Unsubscribe-Post", "List-Unsubscribe=One-Click".into()));
+        }
+        if let Some(issue_id) = self.newsletter_issue_id {
+            headers.push(("X-Newsletter-Issue-Id", issue_id.to_string()));
+        }
+        headers
+    }
+
+    /// Renders the message as a `multipart/alternative` MIME document.
+    pub fn to_mime(&self, sender: &SubscriberEmail) -> Result<lettre::Message, anyhow::Error> {
+        let mut builder = lettre::Message::builder()
+            .from(sender.as_ref().parse::<Mailbox>()?)
+            .to(self.to.as_ref().parse::<Mailbox>()?)
+            .subject(&self.subject);
+        for (name, value) in self.headers() {
+            builder = builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str(name),
+                value,
+            ));
+        }
+        let message = builder.multipart(MultiPart::alternative_plain_html(
+            self.text_body.clone(),
+            self.html_body.clone(),
+        ))?;
+        Ok(message)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailError {
+    #[error("The email provider could not be reached or is temporarily unavailable.")]
+    Transient(#[source] anyhow::Error),
+    #[error("The email provider rejected the message.")]
+    Permanent(#[source] anyhow::Error),
+}
+
+impl EmailError {
+    /// Transient failures are worth another attempt later on, permanent ones
+    /// (e.g. a rejected recipient) will fail again no matter what.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, EmailError::Transient(_))
+    }
+}
+
+/// A way of handing an email over to a provider.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(
+        &self,
+        sender: &SubscriberEmail,
+        message: &EmailMessage,
+    ) -> Result<(), EmailError>;
+
+    /// Sends up to `MAX_BATCH_SIZE` messages, returning one outcome per message
+    /// in the same order. An `Err` means that none of them went out.
+    ///
+    /// Transports without a batch API send the messages one by one.
+    async fn send_batch(
+        &self,
+        sender: &SubscriberEmail,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        let mut outcomes = Vec::with_capacity(messages.len());
+        for message in messages {
+            outcomes.push(self.send(sender, message).await);
+        }
+        Ok(outcomes)
+    }
+}
+
+#[derive(Clone)]
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Arc<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: Arc<dyn EmailTransport>) -> Self {
+        Self { sender, transport }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        let message = EmailMessage {
+            to: recipient.clone(),
+            subject: subject.into(),
+            html_body: html_content.into(),
+            text_body: text_content.into(),
+            unsubscribe_url: None,
+            newsletter_issue_id: None,
+        };
+        self.transport.send(&self.sender, &message).await
+    }
+
+    pub async fn send_email_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        if messages.len() > MAX_BATCH_SIZE {
+            return Err(EmailError::Permanent(anyhow::anyhow!(
+                "A batch can't hold more than {MAX_BATCH_SIZE} messages, got {}.",
+                messages.len()
+            )));
+        }
+        self.transport.send_batch(&self.sender, messages).await
+    }
+}
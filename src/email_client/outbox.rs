@@ -0,0 +1,136 @@
+use super::{EmailError, EmailMessage, EmailTransport};
+use crate::domain::SubscriberEmail;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Writes every message to `directory` as an RFC 5322 `.eml` file with a JSON
+/// sidecar of the same name, instead of handing it over to a provider.
+pub struct OutboxTransport {
+    directory: PathBuf,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct OutboxRecord {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OutboxTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, std::io::Error> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    /// Every message in the outbox, oldest first.
+    pub fn read_all(directory: impl AsRef<Path>) -> Result<Vec<OutboxRecord>, anyhow::Error> {
+        let mut paths = std::fs::read_dir(directory)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|p| p.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|p| Ok(serde_json::from_slice(&std::fs::read(p)?)?))
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for OutboxTransport {
+    async fn send(
+        &self,
+        sender: &SubscriberEmail,
+        message: &EmailMessage,
+    ) -> Result<(), EmailError> {
+        let created_at = Utc::now();
+        let eml = message.to_mime(sender).map_err(EmailError::Permanent)?;
+        let record = OutboxRecord {
+            from: sender.as_ref().to_owned(),
+            to: message.to.as_ref().to_owned(),
+            subject: message.subject.clone(),
+            html_body: message.html_body.clone(),
+            text_body: message.text_body.clone(),
+            headers: message
+                .headers()
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+            created_at,
+        };
+        let json =
+            serde_json::to_vec_pretty(&record).map_err(|e| EmailError::Permanent(e.into()))?;
+        // Timestamp first so that a directory listing is in sending order.
+        let stem = format!(
+            "{}-{}",
+            created_at.format("%Y%m%dT%H%M%S%.6fZ"),
+            Uuid::new_v4()
+        );
+        let base = self.directory.join(stem);
+        tokio::fs::write(base.with_extension("eml"), eml.formatted())
+            .await
+            .map_err(|e| EmailError::Transient(e.into()))?;
+        tokio::fs::write(base.with_extension("json"), json)
+            .await
+            .map_err(|e| EmailError::Transient(e.into()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutboxTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailClient;
+    use claim::assert_ok;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use std::path::PathBuf;
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse_email(SafeEmail().fake()).unwrap()
+    }
+
+    fn outbox_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("outbox-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_and_a_json_sidecar() {
+        let directory = outbox_dir();
+        let transport = OutboxTransport::new(&directory).unwrap();
+        let email_client = EmailClient::new(email(), std::sync::Arc::new(transport));
+        let recipient = email();
+
+        let output = email_client
+            .send_email(
+                &recipient,
+                "Welcome!",
+                "Click <a href=\"http://localhost/confirm\">here</a>",
+                "Visit http://localhost/confirm",
+            )
+            .await;
+
+        assert_ok!(output);
+        let records = OutboxTransport::read_all(&directory).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].to, recipient.as_ref());
+        assert!(records[0].text_body.contains("http://localhost/confirm"));
+        let eml = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.extension().is_some_and(|ext| ext == "eml"))
+            .unwrap();
+        let eml = std::fs::read_to_string(eml).unwrap();
+        assert!(eml.contains(&format!("To: {}", recipient.as_ref())));
+        assert!(eml.contains("Subject: Welcome!"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
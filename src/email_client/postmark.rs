@@ -0,0 +1,460 @@
+use super::{EmailError, EmailMessage, EmailTransport};
+use crate::domain::SubscriberEmail;
+use reqwest::{Client, StatusCode};
+use secrecy::ExposeSecret;
+use std::collections::HashMap;
+
+pub struct PostmarkTransport {
+    http_client: reqwest::Client,
+    base_url: String,
+    authorization_token: secrecy::Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<MessageHeader>,
+    /// Echoed back by Postmark in its webhook payloads.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<&'static str, String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MessageHeader {
+    name: &'static str,
+    value: String,
+}
+
+impl<'a> SendEmailRequest<'a> {
+    fn new(sender: &'a SubscriberEmail, message: &'a EmailMessage) -> Self {
+        Self {
+            from: sender.as_ref(),
+            to: message.to.as_ref(),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            headers: message
+                .headers()
+                .into_iter()
+                .map(|(name, value)| MessageHeader { name, value })
+                .collect(),
+            metadata: message
+                .newsletter_issue_id
+                .map(|issue_id| ("newsletter_issue_id", issue_id.to_string()))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchEmailResult {
+    error_code: i64,
+    message: String,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: secrecy::Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(
+        &self,
+        sender: &SubscriberEmail,
+        message: &EmailMessage,
+    ) -> Result<(), EmailError> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendEmailRequest::new(sender, message);
+        self.http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(classify_error)?;
+        Ok(())
+    }
+
+    async fn send_batch(
+        &self,
+        sender: &SubscriberEmail,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        let url = format!("{}/email/batch", self.base_url);
+        let request_body: Vec<_> = messages
+            .iter()
+            .map(|message| SendEmailRequest::new(sender, message))
+            .collect();
+        let results: Vec<BatchEmailResult> = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(classify_error)?
+            .json()
+            .await
+            .map_err(classify_error)?;
+        if results.len() != messages.len() {
+            return Err(EmailError::Permanent(anyhow::anyhow!(
+                "Postmark returned {} results for a batch of {} messages.",
+                results.len(),
+                messages.len()
+            )));
+        }
+        // A non-zero error code means Postmark refused that message (inactive
+        // recipient, invalid address, ...), which a retry won't fix.
+        let outcomes = results
+            .into_iter()
+            .map(|result| match result.error_code {
+                0 => Ok(()),
+                code => Err(EmailError::Permanent(anyhow::anyhow!(
+                    "Postmark error {code}: {}",
+                    result.message
+                ))),
+            })
+            .collect();
+        Ok(outcomes)
+    }
+}
+
+/// Timeouts, connection failures, rate limiting and 5xx responses are worth
+/// another attempt; anything else (e.g. a 422 for a rejected recipient) is not.
+fn classify_error(e: reqwest::Error) -> EmailError {
+    let is_transient = match e.status() {
+        Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        None => true,
+    };
+    if is_transient {
+        EmailError::Transient(e.into())
+    } else {
+        EmailError::Permanent(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostmarkTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, EmailMessage};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::{
+        internet::en::SafeEmail,
+        lorem::en::{Paragraph, Sentence},
+    };
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn paragraph() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email_generator() -> SubscriberEmail {
+        SubscriberEmail::parse_email(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        let transport = PostmarkTransport::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_secs(180),
+        );
+        EmailClient::new(email_generator(), std::sync::Arc::new(transport))
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let _ = email_client
+            .send_email(&email_generator(), &subject(), &paragraph(), &paragraph())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let subject = subject();
+        let content = paragraph();
+
+        let output = email_client
+            .send_email(&email_generator(), &subject, &content, &content)
+            .await;
+
+        assert_ok!(output);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let subject = subject();
+        let content = paragraph();
+
+        let output = email_client
+            .send_email(&email_generator(), &subject, &content, &content)
+            .await;
+
+        assert_err!(output);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_server_times_out() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let subject = subject();
+        let content = paragraph();
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let output = email_client
+            .send_email(&email_generator(), &subject, &content, &content)
+            .await;
+
+        assert_err!(output);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_transient() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let content = paragraph();
+
+        let output = email_client
+            .send_email(&email_generator(), &subject(), &content, &content)
+            .await;
+
+        assert!(assert_err!(output).is_transient());
+    }
+
+    #[tokio::test]
+    async fn rejected_messages_are_permanent_failures() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let content = paragraph();
+
+        let output = email_client
+            .send_email(&email_generator(), &subject(), &content, &content)
+            .await;
+
+        assert!(!assert_err!(output).is_transient());
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            to: email_generator(),
+            subject: subject(),
+            html_body: paragraph(),
+            text_body: paragraph(),
+            unsubscribe_url: None,
+            newsletter_issue_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_sends_all_messages_in_one_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .and(|request: &Request| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&request.body)
+                    .is_ok_and(|body| body.len() == 2)
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 0, "Message": "OK" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_email_batch(&[message(), message()]).await;
+
+        let outcomes = assert_ok!(outcomes);
+        assert!(outcomes.iter().all(|o| o.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_reports_per_message_failures() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_email_batch(&[message(), message()]).await;
+
+        let outcomes = assert_ok!(outcomes);
+        assert_ok!(&outcomes[0]);
+        assert!(!assert_err!(&outcomes[1]).is_transient());
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_as_a_whole_if_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_email_batch(&[message()]).await;
+
+        assert!(assert_err!(outcomes).is_transient());
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_links_are_sent_as_list_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(path("/email/batch"))
+            .and(|request: &Request| {
+                let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+                body[0]["Headers"]
+                    == serde_json::json!([
+                        { "Name": "List-Unsubscribe", "Value": "<https://example.com/u>" },
+                        { "Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click" },
+                    ])
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let message = EmailMessage {
+            unsubscribe_url: Some("https://example.com/u".into()),
+            ..message()
+        };
+
+        let outcomes = email_client.send_email_batch(&[message]).await;
+
+        assert_ok!(outcomes);
+    }
+
+    #[tokio::test]
+    async fn issue_ids_are_sent_as_metadata() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let issue_id = uuid::Uuid::new_v4();
+        Mock::given(path("/email/batch"))
+            .and(move |request: &Request| {
+                let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+                body[0]["Metadata"] == serde_json::json!({ "newsletter_issue_id": issue_id })
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let message = EmailMessage {
+            newsletter_issue_id: Some(issue_id),
+            ..message()
+        };
+
+        let outcomes = email_client.send_email_batch(&[message]).await;
+
+        assert_ok!(outcomes);
+    }
+}
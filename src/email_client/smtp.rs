@@ -0,0 +1,192 @@
+use super::{EmailError, EmailMessage, EmailTransport};
+use crate::domain::SubscriberEmail;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain-text connection, only meant for relays on a trusted network.
+    None,
+    /// Upgrade the connection with `STARTTLS` (usually port 587).
+    #[default]
+    StartTls,
+    /// TLS from the first byte (usually port 465).
+    Implicit,
+}
+
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder
+                .credentials(Credentials::new(
+                    username,
+                    password.expose_secret().to_owned(),
+                ))
+                .authentication(vec![Mechanism::Plain, Mechanism::Login]);
+        }
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(
+        &self,
+        sender: &SubscriberEmail,
+        message: &EmailMessage,
+    ) -> Result<(), EmailError> {
+        let message = message.to_mime(sender).map_err(EmailError::Permanent)?;
+        self.mailer.send(message).await.map_err(|e| {
+            // 5xx replies won't change on retry, 4xx replies and I/O errors might.
+            if e.is_permanent() {
+                EmailError::Permanent(e.into())
+            } else {
+                EmailError::Transient(e.into())
+            }
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmtpTls, SmtpTransport};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailClient;
+    use base64::{engine::general_purpose, Engine};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::{
+        internet::en::SafeEmail,
+        lorem::en::{Paragraph, Sentence},
+    };
+    use fake::Fake;
+    use secrecy::Secret;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// A bare-bones SMTP relay accepting a single session. It answers every
+    /// `RCPT TO` with `rcpt_reply` and hands back the transcript of the session.
+    async fn fake_smtp_server(rcpt_reply: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut transcript = String::new();
+            let mut in_data = false;
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                transcript.push_str(&line);
+                transcript.push('\n');
+                let reply = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    "250 Queued"
+                } else if line.starts_with("EHLO") {
+                    "250-localhost\r\n250 AUTH PLAIN LOGIN"
+                } else if line.starts_with("AUTH") {
+                    "235 Authentication successful"
+                } else if line.starts_with("RCPT") {
+                    rcpt_reply
+                } else if line == "DATA" {
+                    in_data = true;
+                    "354 End data with <CR><LF>.<CR><LF>"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    "250 OK"
+                };
+                writer
+                    .write_all(format!("{reply}\r\n").as_bytes())
+                    .await
+                    .unwrap();
+            }
+            transcript
+        });
+        (port, handle)
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse_email(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(port: u16) -> EmailClient {
+        let transport = SmtpTransport::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            Some(("user".into(), Secret::new("password".into()))),
+            std::time::Duration::from_secs(10),
+        )
+        .unwrap();
+        EmailClient::new(email(), std::sync::Arc::new(transport))
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_to_the_relay() {
+        let (port, server) = fake_smtp_server("250 OK").await;
+        let recipient = email();
+        let subject: String = Sentence(1..2).fake();
+        let content: String = Paragraph(1..10).fake();
+
+        let output = email_client(port)
+            .send_email(&recipient, &subject, &content, &content)
+            .await;
+
+        assert_ok!(output);
+        let transcript = server.await.unwrap();
+        let credentials = general_purpose::STANDARD.encode("\0user\0password");
+        assert!(transcript.contains(&format!("AUTH PLAIN {credentials}")));
+        assert!(transcript.contains(&format!("RCPT TO:<{}>", recipient.as_ref())));
+        assert!(transcript.contains("Content-Type: multipart/alternative"));
+    }
+
+    #[tokio::test]
+    async fn rejected_recipients_are_permanent_failures() {
+        let (port, _server) = fake_smtp_server("550 No such user").await;
+        let content: String = Paragraph(1..10).fake();
+
+        let output = email_client(port)
+            .send_email(&email(), "Subject", &content, &content)
+            .await;
+
+        assert!(!assert_err!(output).is_transient());
+    }
+
+    #[tokio::test]
+    async fn temporary_rejections_are_transient_failures() {
+        let (port, _server) = fake_smtp_server("451 Try again later").await;
+        let content: String = Paragraph(1..10).fake();
+
+        let output = email_client(port)
+            .send_email(&email(), "Subject", &content, &content)
+            .await;
+
+        assert!(assert_err!(output).is_transient());
+    }
+}
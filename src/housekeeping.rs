@@ -0,0 +1,32 @@
+use crate::routes::sweep_stale_tokens;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::watch;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How long expired or used subscription tokens are kept around.
+const TOKEN_RETENTION: chrono::Duration = chrono::Duration::days(7);
+
+/// Periodically deletes data that is no longer needed.
+pub async fn housekeeping_loop(
+    pool: PgPool,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        if *shutdown.borrow() || shutdown.has_changed().is_err() {
+            return Ok(());
+        }
+        match sweep_stale_tokens(&pool, TOKEN_RETENTION).await {
+            Ok(n_deleted) => tracing::info!(n_deleted, "Swept stale subscription tokens"),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to sweep stale subscription tokens",
+            ),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(SWEEP_INTERVAL) => {}
+            _ = shutdown.changed() => {}
+        }
+    }
+}
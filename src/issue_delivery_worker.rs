@@ -1,20 +1,83 @@
 #![allow(unused_variables)]
-use crate::config::Settings;
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
-use secrecy::ExposeSecret;
-use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
-use sqlx::PgPool;
+use crate::config::{Settings, WorkerSettings};
+use crate::confirmation_queue::confirmation_queue_loop;
+use crate::housekeeping::housekeeping_loop;
+use crate::routes::{preferences_link, unsubscribe_link};
+use crate::{
+    domain::{SegmentFilter, SubscriberEmail},
+    email_client::{EmailClient, EmailError, EmailMessage, MAX_BATCH_SIZE},
+    issue_template::{IssueTemplate, Recipient, TemplateContext},
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::postgres::{PgConnectOptions, PgListener, PgPoolOptions};
+use sqlx::{PgPool, QueryBuilder};
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::{field::display, Span};
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinSet;
+use tracing::Span;
 use uuid::Uuid;
 
 pub type PgTransaction = sqlx::Transaction<'static, sqlx::Postgres>;
 
-#[allow(dead_code)]
+/// Postgres channel notified whenever new rows land in `issue_delivery_queue`
+/// or an issue gets (re)scheduled.
+pub const ISSUE_DELIVERY_CHANNEL: &str = "issue_delivery_queue";
+
+pub const MAX_RETRIES: i16 = 8;
+const BASE_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
 struct NewsletterIssue {
     title: String,
-    html_content: String,
-    text_content: String,
+    /// `Err` for issues whose content doesn't compile, which publishing
+    /// normally prevents.
+    template: Result<IssueTemplate, String>,
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    /// `None` once the address is no longer a confirmed member of the issue's
+    /// list, or while they paused their subscription.
+    subscriber_id: Option<Uuid>,
+    subscriber_name: Option<String>,
+    subscriber_attributes: Option<serde_json::Value>,
+    /// Set if the address was suppressed after the task was enqueued.
+    suppression: Option<String>,
+}
+
+/// What the workers need to fill in the footer of every message: the
+/// personalised unsubscribe and preferences links and the postal address.
+#[derive(Clone)]
+struct Footer {
+    base_url: String,
+    hmac_secret: Secret<String>,
+    postal_address: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DeliveryOutcome {
+    Sent,
+    Deferred,
+    Failed,
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Deferred => "deferred",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Skipped => "skipped",
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -23,12 +86,87 @@ enum ExecutionOutcome {
     EmptyQueue,
 }
 
+/// Enqueues one delivery task per confirmed member of the issue's list (and
+/// segment, if it targets one), leaving out suppressed addresses, and wakes
+/// the workers up once the transaction commits.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    tx: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let segment = sqlx::query_scalar!(
+        r#"
+        SELECT s.filter
+        FROM newsletter_issues i
+        JOIN segments s ON s.segment_id = i.segment_id
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .map(|filter| SegmentFilter::parse(&filter).map_err(anyhow::Error::msg))
+    .transpose()?;
+    let mut query = QueryBuilder::new(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT i.newsletter_issue_id, s.email
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        JOIN newsletter_issues i ON i.list_id = ls.list_id
+        WHERE ls.status = 'confirmed'
+            AND s.status = 'confirmed'
+            AND (s.paused_until IS NULL OR s.paused_until <= now())
+            AND NOT EXISTS (
+                SELECT 1 FROM suppressions x WHERE x.email_hash = hash_email(s.email)
+            )
+            AND i.newsletter_issue_id = "#,
+    );
+    query.push_bind(newsletter_issue_id);
+    if let Some(segment) = segment {
+        segment.push_conditions(&mut query);
+    }
+    let n_total = query.build().execute(&mut **tx).await?.rows_affected();
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET n_total = $2
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        n_total as i32
+    )
+    .execute(&mut **tx)
+    .await?;
+    notify_workers(tx, newsletter_issue_id).await?;
+    Ok(())
+}
+
+/// Delivered on commit, waking up idle workers and the scheduler right away.
+pub async fn notify_workers(
+    tx: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "SELECT pg_notify($1, $2)",
+        ISSUE_DELIVERY_CHANNEL,
+        newsletter_issue_id.to_string()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+    let issue = sqlx::query!(
         r#"
-        SELECT title, html_content, text_content
+        SELECT
+            title,
+            html_content,
+            text_content,
+            layout_id,
+            markdown_content IS NOT NULL AS "from_markdown!"
         FROM newsletter_issues
         WHERE newsletter_issue_id = $1
         "#,
@@ -36,137 +174,644 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     )
     .fetch_one(pool)
     .await?;
-    Ok(issue)
+    let layout = get_layout(pool, issue.layout_id).await?;
+    let template = IssueTemplate::new(&issue.html_content, &issue.text_content).map(|t| {
+        if issue.from_markdown {
+            t.sanitized()
+        } else {
+            t
+        }
+    });
+    Ok(NewsletterIssue {
+        title: issue.title,
+        template: match layout {
+            Some(layout) => template.and_then(|t| t.with_layout(&layout)),
+            None => template,
+        },
+    })
 }
 
-#[tracing::instrument(
-    skip_all,
-    fields(
-        newsletter_issue_id = tracing::field::Empty,
-        subscriber_email = tracing::field::Empty),
-    err
-)]
+/// The HTML of the layout an issue uses: the one it was given, or else the
+/// default one, if any.
+#[tracing::instrument(name = "Get an issue's layout", skip(pool))]
+pub async fn get_layout(
+    pool: &PgPool,
+    layout_id: Option<Uuid>,
+) -> Result<Option<String>, anyhow::Error> {
+    let html = sqlx::query_scalar!(
+        r#"
+        SELECT html
+        FROM layouts
+        WHERE layout_id = $1 OR ($1 IS NULL AND is_default)
+        "#,
+        layout_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the layout from db.")?;
+    Ok(html)
+}
+
+/// Renders the issue for one recipient.
+fn build_message(
+    task: &Task,
+    subscriber_id: Uuid,
+    issue: &NewsletterIssue,
+    footer: &Footer,
+) -> Result<EmailMessage, String> {
+    let email = SubscriberEmail::parse_email(task.subscriber_email.clone())?;
+    let template = issue.template.as_ref().map_err(Clone::clone)?;
+    let unsubscribe_url = unsubscribe_link(&footer.base_url, subscriber_id, &footer.hmac_secret);
+    let preferences_url = preferences_link(&footer.base_url, subscriber_id, &footer.hmac_secret);
+    let context = TemplateContext {
+        subscriber: Recipient {
+            name: task.subscriber_name.as_deref().unwrap_or_default(),
+            email: email.as_ref(),
+            attributes: task
+                .subscriber_attributes
+                .as_ref()
+                .unwrap_or(&serde_json::Value::Null),
+        },
+        unsubscribe_url: &unsubscribe_url,
+        preferences_url: &preferences_url,
+        postal_address: &footer.postal_address,
+    };
+    let (html_body, text_body) = template
+        .render_email(&context)
+        .map_err(|e| format!("Failed to render the issue: {e}"))?;
+    Ok(EmailMessage {
+        to: email,
+        subject: issue.title.clone(),
+        html_body,
+        text_body,
+        unsubscribe_url: Some(unsubscribe_url),
+        newsletter_issue_id: Some(task.newsletter_issue_id),
+    })
+}
+
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
 async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    footer: &Footer,
+    batch_size: usize,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    let (mut transaction, tasks) = dequeue_tasks(pool, batch_size).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
-    if let Some((transaction, issue_id, email)) = dequeue_task(pool).await? {
-        Span::current()
-            .record("newsletter_issue_id", &display(issue_id))
-            .record("subscriber_email", &display(&email));
-        match SubscriberEmail::parse_email(email.clone()) {
-            Ok(email) => {
-                let issue = get_issue(pool, issue_id).await?;
-                if let Err(e) = email_client
-                    .send_email(
-                        &email,
-                        &issue.title,
-                        &issue.html_content,
-                        &issue.text_content,
-                    )
-                    .await
-                {
-                    tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. Skipping.",
-                    );
-                }
+    Span::current().record("n_tasks", tasks.len());
+    let mut issues: HashMap<Uuid, NewsletterIssue> = HashMap::new();
+    let mut deliverable = Vec::with_capacity(tasks.len());
+    let mut messages = Vec::with_capacity(tasks.len());
+    let mut outcomes = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        if let Some(reason) = &task.suppression {
+            delete_task(&mut transaction, &task).await?;
+            let detail = format!("Suppressed ({reason})");
+            log_delivery(
+                &mut transaction,
+                &task,
+                DeliveryOutcome::Skipped,
+                Some(&detail),
+            )
+            .await?;
+            outcomes.push((task.newsletter_issue_id, DeliveryOutcome::Skipped));
+            continue;
+        }
+        let Some(subscriber_id) = task.subscriber_id else {
+            delete_task(&mut transaction, &task).await?;
+            log_delivery(
+                &mut transaction,
+                &task,
+                DeliveryOutcome::Skipped,
+                Some("Unsubscribed or paused"),
+            )
+            .await?;
+            outcomes.push((task.newsletter_issue_id, DeliveryOutcome::Skipped));
+            continue;
+        };
+        let issue = match issues.entry(task.newsletter_issue_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(get_issue(pool, task.newsletter_issue_id).await?),
+        };
+        match build_message(&task, subscriber_id, issue, footer) {
+            Ok(message) => {
+                messages.push(message);
+                deliverable.push(task);
             }
             Err(e) => {
                 tracing::error!(
-                error.cause_chain = ?e,
                 error.message = %e,
-                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+                newsletter_issue_id = %task.newsletter_issue_id,
+                subscriber_email = %task.subscriber_email,
+                "Skipping a confirmed subscriber. Their copy of the issue could not be built",
                 );
+                dead_letter_task(&mut transaction, &task, &e).await?;
+                log_delivery(&mut transaction, &task, DeliveryOutcome::Failed, Some(&e)).await?;
+                outcomes.push((task.newsletter_issue_id, DeliveryOutcome::Failed));
             }
         }
-        delete_task(transaction, issue_id, &email).await?;
     }
+    if !messages.is_empty() {
+        match email_client.send_email_batch(&messages).await {
+            Ok(results) => {
+                for (task, result) in deliverable.iter().zip(results) {
+                    let outcome =
+                        settle_task(&mut transaction, task, result.err().as_ref()).await?;
+                    outcomes.push((task.newsletter_issue_id, outcome));
+                }
+            }
+            Err(e) => {
+                for task in &deliverable {
+                    let outcome = settle_task(&mut transaction, task, Some(&e)).await?;
+                    outcomes.push((task.newsletter_issue_id, outcome));
+                }
+            }
+        }
+    }
+    update_progress(&mut transaction, &outcomes).await?;
+    transaction.commit().await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// Records the outcome of a delivery attempt: delivered tasks are removed from
+/// the queue, transient failures are rescheduled and the rest are dead-lettered.
+/// Every attempt ends up in the delivery log.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id = %task.newsletter_issue_id,
+        subscriber_email = %task.subscriber_email),
+)]
+async fn settle_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    error: Option<&EmailError>,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let detail = error.map(describe_error);
+    let outcome = match error {
+        None => {
+            delete_task(transaction, task).await?;
+            DeliveryOutcome::Sent
+        }
+        Some(e) if e.is_transient() && task.n_retries < MAX_RETRIES => {
+            tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            n_retries = task.n_retries,
+            "Failed to deliver issue to a confirmed subscriber. Retrying later.",
+            );
+            retry_task(transaction, task).await?;
+            DeliveryOutcome::Deferred
+        }
+        Some(e) => {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to deliver issue to a confirmed subscriber. Moving it to the dead letters.",
+            );
+            dead_letter_task(transaction, task, &describe_error(e)).await?;
+            DeliveryOutcome::Failed
+        }
+    };
+    log_delivery(transaction, task, outcome, detail.as_deref()).await?;
+    Ok(outcome)
+}
+
+fn describe_error(e: &EmailError) -> String {
+    match std::error::Error::source(e) {
+        Some(source) => format!("{e} {source}"),
+        None => e.to_string(),
+    }
+}
+
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut tx: PgTransaction,
-    issue_id: Uuid,
-    subscriber_email: &str,
+async fn log_delivery(
+    tx: &mut PgTransaction,
+    task: &Task,
+    outcome: DeliveryOutcome,
+    detail: Option<&str>,
 ) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            detail,
+            logged_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        outcome.as_str(),
+        detail
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Bumps the progress counters once per issue rather than once per
+/// task, to keep the issue rows locked for as short as possible.
+#[tracing::instrument(skip_all)]
+async fn update_progress(
+    tx: &mut PgTransaction,
+    outcomes: &[(Uuid, DeliveryOutcome)],
+) -> Result<(), anyhow::Error> {
+    let mut progress: HashMap<Uuid, (i32, i32, i32)> = HashMap::new();
+    for (issue_id, outcome) in outcomes {
+        let (n_sent, n_failed, n_skipped) = progress.entry(*issue_id).or_default();
+        match outcome {
+            DeliveryOutcome::Sent => *n_sent += 1,
+            DeliveryOutcome::Failed => *n_failed += 1,
+            // Skipped recipients are no longer part of the audience.
+            DeliveryOutcome::Skipped => *n_skipped += 1,
+            DeliveryOutcome::Deferred => {}
+        }
+    }
+    for (issue_id, (n_sent, n_failed, n_skipped)) in progress {
+        if n_sent == 0 && n_failed == 0 && n_skipped == 0 {
+            continue;
+        }
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET n_sent = n_sent + $2, n_failed = n_failed + $3, n_total = n_total - $4
+            WHERE newsletter_issue_id = $1
+            "#,
+            issue_id,
+            n_sent,
+            n_failed,
+            n_skipped
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Exponential backoff with jitter: the delay doubles with every retry, is capped
+/// at `MAX_BACKOFF` and is then drawn uniformly from its upper half so that tasks
+/// failing together don't all come back at the same instant.
+pub fn backoff(n_retries: i16) -> Duration {
+    let exponent = n_retries.clamp(0, 16) as u32;
+    let delay = BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(MAX_BACKOFF);
+    let delay_ms = delay.as_millis() as u64;
+    Duration::from_millis(thread_rng().gen_range(delay_ms / 2..=delay_ms))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(tx: &mut PgTransaction, task: &Task) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
         WHERE newsletter_issue_id = $1 AND subscriber_email = $2
         "#,
-        issue_id,
-        subscriber_email
+        task.newsletter_issue_id,
+        task.subscriber_email
     )
-    .execute(&mut *tx)
+    .execute(&mut **tx)
     .await?;
-    tx.commit().await?;
     Ok(())
 }
 
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn retry_task(tx: &mut PgTransaction, task: &Task) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff(task.n_retries))?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_retries = n_retries + 1, execute_after = $3
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn dead_letter_task(
+    tx: &mut PgTransaction,
+    task: &Task,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET n_attempts = EXCLUDED.n_attempts,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        task.n_retries + 1,
+        error
+    )
+    .execute(&mut **tx)
+    .await?;
+    delete_task(tx, task).await
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    max_tasks: usize,
+) -> Result<(PgTransaction, Vec<Task>), anyhow::Error> {
     let mut tx = pool.begin().await?;
-    let res = sqlx::query!(
+    let tasks = sqlx::query_as!(
+        Task,
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            s.id AS "subscriber_id?",
+            s.name AS "subscriber_name?",
+            s.attributes AS "subscriber_attributes?",
+            x.reason AS "suppression?"
+        FROM issue_delivery_queue q
+        LEFT JOIN suppressions x ON x.email_hash = hash_email(q.subscriber_email)
+        LEFT JOIN subscriptions s ON s.email = q.subscriber_email
+            AND s.status = 'confirmed'
+            AND (s.paused_until IS NULL OR s.paused_until <= now())
+            AND EXISTS (
+                SELECT 1
+                FROM list_subscriptions ls
+                JOIN newsletter_issues i ON i.list_id = ls.list_id
+                WHERE i.newsletter_issue_id = q.newsletter_issue_id
+                    AND ls.subscriber_id = s.id
+                    AND ls.status = 'confirmed'
+            )
+        WHERE q.execute_after <= now()
+            AND q.newsletter_issue_id IN (
+                SELECT newsletter_issue_id
+                FROM newsletter_issues
+                WHERE status = 'sending'
+            )
+        FOR UPDATE OF q
         SKIP LOCKED
-        LIMIT 1
+        LIMIT $1
     "#,
+        max_tasks as i64
     )
-    .fetch_optional(&mut *tx)
+    .fetch_all(&mut *tx)
     .await?;
-    if let Some(res) = res {
-        Ok(Some((tx, res.newsletter_issue_id, res.subscriber_email)))
-    } else {
-        Ok(None)
+    Ok((tx, tasks))
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    footer: Footer,
+    settings: WorkerSettings,
+    wake_up: Arc<Notify>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    let batch_size = settings.batch_size.clamp(1, MAX_BATCH_SIZE);
+    loop {
+        // Checked between tasks only, so that a batch being sent always gets
+        // its transaction committed before the worker stops.
+        if *shutdown.borrow() || shutdown.has_changed().is_err() {
+            return Ok(());
+        }
+        // Registered before looking at the queue, so that tasks enqueued while
+        // we are busy still cut the following pause short.
+        let woken_up = wake_up.notified();
+        tokio::pin!(woken_up);
+        woken_up.as_mut().enable();
+        let pause = match try_execute_task(&pool, &email_client, &footer, batch_size).await {
+            Ok(ExecutionOutcome::EmptyQueue) => settings.empty_queue_poll_interval(),
+            Err(_) => settings.error_backoff(),
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = &mut woken_up => {}
+            _ = shutdown.changed() => {}
+        }
     }
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+/// Starts sending scheduled issues whose publication time has come. Returns
+/// when the next scheduled issue is due, if any.
+#[tracing::instrument(skip_all)]
+async fn enqueue_due_issues(pool: &PgPool) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    let due = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND published_at <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        "#
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for issue in due {
+        tracing::info!(newsletter_issue_id = %issue.newsletter_issue_id, "Publishing scheduled issue");
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'sending'
+            WHERE newsletter_issue_id = $1
+            "#,
+            issue.newsletter_issue_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        enqueue_delivery_tasks(&mut tx, issue.newsletter_issue_id).await?;
+    }
+    let next = sqlx::query_scalar!(
+        r#"
+        SELECT min(published_at)
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND published_at > now()
+        "#
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(next)
+}
+
+async fn scheduler_loop(
+    pool: PgPool,
+    settings: WorkerSettings,
+    wake_up: Arc<Notify>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(15)).await;
+        if *shutdown.borrow() || shutdown.has_changed().is_err() {
+            return Ok(());
+        }
+        let woken_up = wake_up.notified();
+        tokio::pin!(woken_up);
+        woken_up.as_mut().enable();
+        let pause = match enqueue_due_issues(&pool).await {
+            Ok(next) => {
+                let poll_interval = settings.empty_queue_poll_interval();
+                next.and_then(|next| (next - Utc::now()).to_std().ok())
+                    .map_or(poll_interval, |until_next| until_next.min(poll_interval))
             }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+            Err(e) => {
+                tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to publish scheduled issues",
+                );
+                settings.error_backoff()
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = &mut woken_up => {}
+            _ = shutdown.changed() => {}
         }
     }
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let conn_pool = PgPoolOptions::new().connect_lazy_with(
-        PgConnectOptions::new()
-            .host(&configuration.database.host)
-            .port(configuration.database.port)
-            .username(&configuration.database.username)
-            .password(configuration.database.password.expose_secret())
-            .database(&configuration.database.name),
-    );
-    let sender_email = configuration
-        .email_client
-        .sender()
-        .expect("Invalid sender email address.");
-    let timeout = configuration.email_client.timeout();
-    let email_client = EmailClient::new(
-        configuration.email_client.base_url,
-        sender_email,
-        configuration.email_client.authorization_token,
-        timeout,
-    );
-    worker_loop(conn_pool, email_client).await
+/// Wakes idle workers up as soon as new delivery tasks are enqueued. Workers
+/// keep polling on their own while the listener connection is down.
+async fn listen_for_new_tasks(
+    pool: PgPool,
+    wake_up: Arc<Notify>,
+    reconnect_after: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        let outcome = tokio::select! {
+            outcome = forward_notifications(&pool, &wake_up) => outcome,
+            _ = shutdown.changed() => return Ok(()),
+        };
+        if let Err(e) = outcome {
+            tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Stopped listening for new delivery tasks. Falling back to polling.",
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(reconnect_after) => {}
+            _ = shutdown.changed() => return Ok(()),
+        }
+    }
+}
+
+async fn forward_notifications(pool: &PgPool, wake_up: &Notify) -> Result<(), anyhow::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(ISSUE_DELIVERY_CHANNEL).await?;
+    loop {
+        // Also wakes the workers up when we start listening and after the
+        // listener reconnected (`None`), since notifications may have been
+        // missed in the meantime.
+        wake_up.notify_waiters();
+        listener.try_recv().await?;
+    }
+}
+
+/// Runs `worker.n_workers` concurrent workers until `shutdown` flips to `true`
+/// (or its sender goes away), then waits for every in-flight task to settle.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    let settings = configuration.worker;
+    let n_workers = settings.n_workers.max(1);
+    // Each worker holds a connection for its transaction and needs another
+    // one to look up issues. The scheduler, the listener, housekeeping and
+    // the confirmation emails get one each.
+    let conn_pool = PgPoolOptions::new()
+        .max_connections(2 * n_workers as u32 + 4)
+        .connect_lazy_with(
+            PgConnectOptions::new()
+                .host(&configuration.database.host)
+                .port(configuration.database.port)
+                .username(&configuration.database.username)
+                .password(configuration.database.password.expose_secret())
+                .database(&configuration.database.name),
+        );
+    let email_client = configuration.email_client.client();
+    let footer = Footer {
+        base_url: configuration.app_settings.base_url.clone(),
+        hmac_secret: configuration.app_settings.hmac_secret,
+        postal_address: configuration.app_settings.postal_address,
+    };
+    let wake_up = Arc::new(Notify::new());
+    let mut workers = JoinSet::new();
+    workers.spawn(housekeeping_loop(conn_pool.clone(), shutdown.clone()));
+    workers.spawn(confirmation_queue_loop(
+        conn_pool.clone(),
+        email_client.clone(),
+        configuration.app_settings.base_url,
+        settings.error_backoff(),
+        shutdown.clone(),
+    ));
+    workers.spawn(scheduler_loop(
+        conn_pool.clone(),
+        settings.clone(),
+        wake_up.clone(),
+        shutdown.clone(),
+    ));
+    workers.spawn(listen_for_new_tasks(
+        conn_pool.clone(),
+        wake_up.clone(),
+        settings.error_backoff(),
+        shutdown.clone(),
+    ));
+    for _ in 0..n_workers {
+        workers.spawn(worker_loop(
+            conn_pool.clone(),
+            email_client.clone(),
+            footer.clone(),
+            settings.clone(),
+            wake_up.clone(),
+            shutdown.clone(),
+        ));
+    }
+    while let Some(outcome) = workers.join_next().await {
+        outcome??;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, BASE_BACKOFF, MAX_BACKOFF};
+
+    #[test]
+    fn first_retry_waits_at_most_the_base_backoff() {
+        let delay = backoff(0);
+        assert!(delay >= BASE_BACKOFF / 2 && delay <= BASE_BACKOFF);
+    }
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let delay = backoff(3);
+        assert!(delay >= BASE_BACKOFF * 4 && delay <= BASE_BACKOFF * 8);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        for n_retries in [12, 16, i16::MAX] {
+            assert!(backoff(n_retries) <= MAX_BACKOFF);
+        }
+    }
 }
@@ -0,0 +1,289 @@
+//! Per-recipient placeholders in issue content, such as
+//! `{{ subscriber.name }}`, `{{ subscriber.attributes.region }}` or
+//! `{{ unsubscribe_url }}`, and the layouts wrapped around the HTML body.
+use crate::utils::html_escape;
+use minijinja::{context, value::Value, AutoEscape, Environment};
+
+const HTML: &str = "html";
+const TEXT: &str = "text";
+const LAYOUT: &str = "layout";
+/// The variables templates can refer to, see `TemplateContext`.
+const VARIABLES: [&str; 4] = [
+    "subscriber",
+    "unsubscribe_url",
+    "preferences_url",
+    "postal_address",
+];
+/// Where a layout puts the body of the issue.
+const CONTENT: &str = "content";
+
+#[derive(serde::Serialize)]
+pub struct Recipient<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub attributes: &'a serde_json::Value,
+}
+
+#[derive(serde::Serialize)]
+pub struct TemplateContext<'a> {
+    pub subscriber: Recipient<'a>,
+    pub unsubscribe_url: &'a str,
+    pub preferences_url: &'a str,
+    /// The sender's postal address, required in the footer of every issue.
+    pub postal_address: &'a str,
+}
+
+/// The HTML and plain text bodies of an issue, compiled once and rendered
+/// for every recipient. Values are HTML-escaped in the HTML body only.
+pub struct IssueTemplate {
+    env: Environment<'static>,
+    has_layout: bool,
+    sanitize: bool,
+}
+
+impl IssueTemplate {
+    /// Fails on syntax errors and on unknown variables, with a message meant
+    /// for whoever wrote the issue.
+    pub fn new(html_content: &str, text_content: &str) -> Result<Self, String> {
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(|name| match name {
+            HTML | LAYOUT => AutoEscape::Html,
+            _ => AutoEscape::None,
+        });
+        add_template(&mut env, HTML, html_content, "HTML content", &VARIABLES)?;
+        add_template(
+            &mut env,
+            TEXT,
+            text_content,
+            "plain text content",
+            &VARIABLES,
+        )?;
+        Ok(Self {
+            env,
+            has_layout: false,
+            sanitize: false,
+        })
+    }
+
+    /// Sanitizes the HTML body again once rendered. Markdown is sanitized
+    /// before its template tags are evaluated, and tags such as `| safe`
+    /// could otherwise bring raw HTML back.
+    pub fn sanitized(mut self) -> Self {
+        self.sanitize = true;
+        self
+    }
+
+    /// Wraps the HTML body, footer included, in a layout. The layout puts it
+    /// where it refers to `{{ content }}`.
+    pub fn with_layout(mut self, layout: &str) -> Result<Self, String> {
+        let variables: Vec<_> = VARIABLES.into_iter().chain([CONTENT]).collect();
+        add_template(&mut self.env, LAYOUT, layout, "layout", &variables)?;
+        let template = self.env.get_template(LAYOUT).unwrap();
+        if !template.undeclared_variables(false).contains(CONTENT) {
+            return Err(format!(
+                "The layout has to show the issue with {{{{ {CONTENT} }}}}."
+            ));
+        }
+        self.has_layout = true;
+        Ok(self)
+    }
+
+    /// Checks a layout before it is saved.
+    pub fn check_layout(layout: &str) -> Result<(), String> {
+        Self::new("", "")?.with_layout(layout).map(|_| ())
+    }
+
+    /// Returns the HTML and plain text bodies.
+    pub fn render(&self, context: &TemplateContext) -> Result<(String, String), minijinja::Error> {
+        let mut html = self.env.get_template(HTML)?.render(context)?;
+        if self.sanitize {
+            html = ammonia::clean(&html);
+        }
+        let text = self.env.get_template(TEXT)?.render(context)?;
+        Ok((html, text))
+    }
+
+    /// Renders the bodies as they are sent: followed by the postal address
+    /// and the preferences and unsubscribe links, and within the layout.
+    pub fn render_email(
+        &self,
+        context: &TemplateContext,
+    ) -> Result<(String, String), minijinja::Error> {
+        let (html, text) = self.render(context)?;
+        let TemplateContext {
+            unsubscribe_url,
+            preferences_url,
+            postal_address,
+            ..
+        } = context;
+        let html = format!(
+            r#"{html}<p>{address}</p><p><a href="{preferences_url}">Manage your preferences</a> | <a href="{unsubscribe_url}">Unsubscribe</a></p>"#,
+            address = html_escape(postal_address.trim()).replace('\n', "<br>"),
+        );
+        let html = if self.has_layout {
+            self.env.get_template(LAYOUT)?.render(context! {
+                content => Value::from_safe_string(html),
+                ..Value::from_serialize(context)
+            })?
+        } else {
+            html
+        };
+        Ok((
+            html,
+            format!(
+                "{text}\n\n{}\n\nManage your preferences: {preferences_url}\nUnsubscribe: {unsubscribe_url}",
+                postal_address.trim()
+            ),
+        ))
+    }
+}
+
+/// Compiles a template, refusing variables outside of `variables`.
+fn add_template(
+    env: &mut Environment<'static>,
+    name: &'static str,
+    source: &str,
+    label: &str,
+    variables: &[&str],
+) -> Result<(), String> {
+    env.add_template_owned(name, source.to_owned())
+        .map_err(|e| format!("The {label} is not a valid template: {e}"))?;
+    let template = env.get_template(name).unwrap();
+    let mut unknown: Vec<_> = template
+        .undeclared_variables(false)
+        .into_iter()
+        .filter(|variable| !variables.contains(&variable.as_str()))
+        .collect();
+    unknown.sort();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "The {label} refers to unknown placeholders: {}. Use one of {}.",
+            unknown.join(", "),
+            variables.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueTemplate, Recipient, TemplateContext};
+    use serde_json::json;
+
+    fn with_context<T>(name: &str, f: impl FnOnce(&TemplateContext) -> T) -> T {
+        let attributes = json!({ "region": "emea" });
+        let context = TemplateContext {
+            subscriber: Recipient {
+                name,
+                email: "ursula@example.com",
+                attributes: &attributes,
+            },
+            unsubscribe_url: "https://example.com/unsubscribe?token=a&b",
+            preferences_url: "https://example.com/preferences",
+            postal_address: "1 Main St\nSpringfield",
+        };
+        f(&context)
+    }
+
+    fn render(html: &str, text: &str, name: &str) -> (String, String) {
+        with_context(name, |context| {
+            IssueTemplate::new(html, text)
+                .unwrap()
+                .render(context)
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let (html, text) = render(
+            "<p>Hi {{ subscriber.name }} from {{ subscriber.attributes.region }}</p>",
+            "Hi {{ subscriber.name }}, bye: {{ unsubscribe_url }}",
+            "Ursula",
+        );
+        assert_eq!(html, "<p>Hi Ursula from emea</p>");
+        assert_eq!(
+            text,
+            "Hi Ursula, bye: https://example.com/unsubscribe?token=a&b"
+        );
+    }
+
+    #[test]
+    fn values_are_only_escaped_in_html() {
+        let (html, text) = render(
+            "{{ subscriber.name }}",
+            "{{ subscriber.name }}",
+            "<b>Ursula</b>",
+        );
+        assert_eq!(html, "&lt;b&gt;Ursula&lt;&#x2f;b&gt;");
+        assert_eq!(text, "<b>Ursula</b>");
+    }
+
+    #[test]
+    fn missing_attributes_render_empty_or_default() {
+        let (html, _) = render(
+            "[{{ subscriber.attributes.plan }}] {{ subscriber.attributes.team | default('friend') }}",
+            "",
+            "Ursula",
+        );
+        assert_eq!(html, "[] friend");
+    }
+
+    #[test]
+    fn syntax_errors_are_rejected() {
+        assert!(IssueTemplate::new("Hi {{ subscriber.name", "").is_err());
+        assert!(IssueTemplate::new("", "{% if %}").is_err());
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let e = IssueTemplate::new("Hi {{ name }}", "").err().unwrap();
+        assert!(e.contains("name"));
+    }
+
+    #[test]
+    fn sanitized_templates_drop_raw_html_from_tags() {
+        let html = r#"<p>Hi {{ "<script>alert(1)</script>" | safe }}{% autoescape false %}{{ "<img src=x onerror=alert(1)>" }}{% endautoescape %}</p>"#;
+        let (sanitized, _) = with_context("Ursula", |context| {
+            IssueTemplate::new(html, "")
+                .unwrap()
+                .sanitized()
+                .render(context)
+                .unwrap()
+        });
+        assert_eq!(sanitized, r#"<p>Hi <img src="x"></p>"#);
+    }
+
+    #[test]
+    fn layouts_wrap_the_body_and_footer() {
+        let (html, text) = with_context("Ursula", |context| {
+            IssueTemplate::new("<p>Hi {{ subscriber.name }}</p>", "Hi")
+                .unwrap()
+                .with_layout("<main>{{ content }}</main><p>{{ subscriber.email }}</p>")
+                .unwrap()
+                .render_email(context)
+                .unwrap()
+        });
+        assert_eq!(
+            html,
+            "<main><p>Hi Ursula</p><p>1 Main St<br>Springfield</p>\
+            <p><a href=\"https://example.com/preferences\">Manage your preferences</a> | \
+            <a href=\"https://example.com/unsubscribe?token=a&b\">Unsubscribe</a></p></main>\
+            <p>ursula@example.com</p>"
+        );
+        assert_eq!(
+            text,
+            "Hi\n\n1 Main St\nSpringfield\n\n\
+            Manage your preferences: https://example.com/preferences\n\
+            Unsubscribe: https://example.com/unsubscribe?token=a&b"
+        );
+    }
+
+    #[test]
+    fn layouts_without_a_content_slot_are_rejected() {
+        let template = IssueTemplate::new("", "").unwrap();
+        assert!(template.with_layout("<main></main>").is_err());
+        let template = IssueTemplate::new("", "").unwrap();
+        assert!(template.with_layout("{{ body }}").is_err());
+    }
+}
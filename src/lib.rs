@@ -1,11 +1,17 @@
 pub mod auth;
 pub mod config;
+pub mod confirmation_queue;
 pub mod domain;
 pub mod email_client;
+pub mod housekeeping;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod issue_template;
+pub mod markdown;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
+pub mod subscriber_import;
+pub mod suppression;
 pub mod telemetry;
 pub mod utils;
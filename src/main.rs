@@ -5,7 +5,7 @@ use email_newsletter::{
     telemetry::{get_subscriber, init_subscriber},
 };
 use std::fmt::{Debug, Display};
-use tokio::task::JoinError;
+use tokio::{sync::watch, task::JoinError};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -15,16 +15,59 @@ async fn main() -> anyhow::Result<()> {
 
     let configuration = config::get_configuration().expect("Failed to read configuration.");
     let application: Application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let server_handle = application.server_handle();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut application_task = tokio::spawn(application.run_until_stopped());
+    let mut worker_task = tokio::spawn(run_worker_until_stopped(configuration, shutdown_rx));
 
+    let mut api_exited = false;
+    let mut worker_exited = false;
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
+        _ = shutdown_signal() => tracing::info!("Shutdown signal received, finishing in-flight work"),
+        o = &mut application_task => {
+            report_exit("API", o);
+            api_exited = true;
+        }
+        o = &mut worker_task => {
+            report_exit("Background worker", o);
+            worker_exited = true;
+        }
     };
+
+    // Whichever way we got here, bring the remaining tasks down gracefully.
+    let _ = shutdown_tx.send(true);
+    server_handle.stop(true).await;
+    if !api_exited {
+        report_exit("API", application_task.await);
+    }
+    if !worker_exited {
+        report_exit("Background worker", worker_task.await);
+    }
     Ok(())
 }
 
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install the SIGINT handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(())) => {
@@ -0,0 +1,315 @@
+//! Issues authored in Markdown, rendered once to sanitized HTML and to a
+//! plain-text alternative.
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use uuid::Uuid;
+
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+/// Template tags (`{{ ... }}`, `{% ... %}`) are passed through untouched,
+/// so that they still work inside link destinations.
+pub fn render_markdown(source: &str) -> RenderedMarkdown {
+    let (source, tags) = protect_template_tags(source);
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(&source, options()));
+    let html = ammonia::clean(&unsafe_html);
+    let text = PlainText::render(&source, &tags);
+    RenderedMarkdown {
+        html: restore_template_tags(html, &tags),
+        text: restore_template_tags(text, &tags),
+    }
+}
+
+fn options() -> Options {
+    Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+/// A template tag and the placeholder standing in for it while rendering.
+struct TemplateTag {
+    placeholder: String,
+    tag: String,
+}
+
+/// Swaps template tags for alphanumeric placeholders, which neither the
+/// Markdown renderer nor the sanitizer alter.
+fn protect_template_tags(source: &str) -> (String, Vec<TemplateTag>) {
+    let nonce = Uuid::new_v4().simple().to_string();
+    let mut protected = String::with_capacity(source.len());
+    let mut tags = vec![];
+    let mut rest = source;
+    while let Some(start) = rest.find('{') {
+        let close = match rest.as_bytes().get(start + 1) {
+            Some(b'{') => "}}",
+            Some(b'%') => "%}",
+            _ => {
+                protected.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+        let Some(len) = rest[start + 2..].find(close) else {
+            break;
+        };
+        let end = start + 2 + len + close.len();
+        let placeholder = format!("tmpl{nonce}n{}e", tags.len());
+        protected.push_str(&rest[..start]);
+        protected.push_str(&placeholder);
+        tags.push(TemplateTag {
+            placeholder,
+            tag: rest[start..end].to_owned(),
+        });
+        rest = &rest[end..];
+    }
+    protected.push_str(rest);
+    (protected, tags)
+}
+
+fn restore_template_tags(mut rendered: String, tags: &[TemplateTag]) -> String {
+    for tag in tags {
+        rendered = rendered.replace(&tag.placeholder, &tag.tag);
+    }
+    rendered
+}
+
+/// Renders Markdown as readable plain text. Links are numbered in the text
+/// and listed at the end.
+#[derive(Default)]
+struct PlainText<'a> {
+    tags: &'a [TemplateTag],
+    out: String,
+    /// Links seen so far, in the order they are numbered.
+    links: Vec<String>,
+    /// Destinations and text offsets of the links being rendered.
+    open_links: Vec<(String, usize)>,
+    /// The next item number of each open list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    quote_depth: usize,
+    in_code_block: bool,
+    heading_start: usize,
+    /// Set when the next block shouldn't be preceded by a blank line.
+    tight: bool,
+}
+
+impl<'a> PlainText<'a> {
+    fn render(source: &str, tags: &'a [TemplateTag]) -> String {
+        let mut text = PlainText {
+            tags,
+            ..Default::default()
+        };
+        for event in Parser::new_ext(source, options()) {
+            text.event(event);
+        }
+        let mut out = text.out.trim().to_owned();
+        if !text.links.is_empty() {
+            out.push_str("\n\n");
+            for (i, link) in text.links.iter().enumerate() {
+                out.push_str(&format!("[{}] {link}\n", i + 1));
+            }
+            out.pop();
+        }
+        out
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text),
+            Event::Code(code) => self.out.push_str(&code),
+            Event::SoftBreak | Event::HardBreak => self.newline(),
+            Event::Rule => {
+                self.block();
+                self.out.push_str("----------");
+            }
+            Event::TaskListMarker(checked) => {
+                self.out.push_str(if checked { "[x] " } else { "[ ] " })
+            }
+            // Raw HTML has no plain text equivalent.
+            Event::Html(_) | Event::InlineHtml(_) | Event::FootnoteReference(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock => self.block(),
+            Tag::BlockQuote => {
+                self.block();
+                self.quote_depth += 1;
+                self.out.push_str("> ");
+                self.tight = true;
+            }
+            Tag::Heading { .. } => {
+                self.block();
+                self.heading_start = self.out.len();
+            }
+            Tag::CodeBlock(_) => {
+                self.block();
+                self.in_code_block = true;
+                self.out.push_str("    ");
+            }
+            Tag::List(start) => {
+                if self.lists.is_empty() {
+                    self.block();
+                    self.tight = true;
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                if !self.tight {
+                    self.newline_without_indent();
+                }
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                self.out.push_str(&indent);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_owned(),
+                };
+                self.out.push_str(&marker);
+                self.tight = true;
+            }
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.open_links.push((dest_url.to_string(), self.out.len()));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(level) => {
+                let heading =
+                    restore_template_tags(self.out[self.heading_start..].into(), self.tags);
+                let width = heading.chars().count();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                self.newline();
+                self.out.push_str(&underline.repeat(width));
+            }
+            TagEnd::CodeBlock => {
+                self.in_code_block = false;
+                while self.out.ends_with([' ', '\n']) {
+                    self.out.pop();
+                }
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            TagEnd::BlockQuote => self.quote_depth -= 1,
+            TagEnd::Link | TagEnd::Image => {
+                if let Some((dest_url, start)) = self.open_links.pop() {
+                    // Autolinks already show their destination.
+                    if self.out[start..] != dest_url {
+                        let n = match self.links.iter().position(|l| *l == dest_url) {
+                            Some(i) => i + 1,
+                            None => {
+                                self.links.push(dest_url);
+                                self.links.len()
+                            }
+                        };
+                        self.out.push_str(&format!(" [{n}]"));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let mut lines = text.split('\n').peekable();
+        while let Some(line) = lines.next() {
+            self.out.push_str(line);
+            if lines
+                .peek()
+                .is_some_and(|next| !next.is_empty() || self.in_code_block)
+            {
+                self.newline();
+                if self.in_code_block {
+                    self.out.push_str("    ");
+                }
+            }
+        }
+        self.tight = false;
+    }
+
+    /// Separates a block from the previous one with a blank line.
+    fn block(&mut self) {
+        if self.tight {
+            self.tight = false;
+            return;
+        }
+        if !self.out.is_empty() {
+            self.newline();
+            self.newline();
+        }
+    }
+
+    /// Starts a new line inside the current block quotes and list items.
+    fn newline(&mut self) {
+        self.newline_without_indent();
+        if !self.lists.is_empty() && !self.in_code_block {
+            self.out.push_str(&"  ".repeat(self.lists.len()));
+        }
+    }
+
+    fn newline_without_indent(&mut self) {
+        while self.out.ends_with(' ') && !self.out.ends_with("> ") {
+            self.out.pop();
+        }
+        self.out.push('\n');
+        self.out.push_str(&"> ".repeat(self.quote_depth));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn scripts_are_stripped_from_the_html() {
+        let rendered = render_markdown("Hello <script>alert(1)</script>*world*");
+        assert!(!rendered.html.contains("script"));
+        assert!(rendered.html.contains("<em>world</em>"));
+    }
+
+    #[test]
+    fn links_are_listed_at_the_end_of_the_text() {
+        let rendered = render_markdown(
+            "Read [the post](https://example.com/post) and [more](https://example.com/more).\n\n\
+            Or [the post again](https://example.com/post), or <https://example.com>.",
+        );
+        assert_eq!(
+            rendered.text,
+            "Read the post [1] and more [2].\n\n\
+            Or the post again [1], or https://example.com.\n\n\
+            [1] https://example.com/post\n\
+            [2] https://example.com/more"
+        );
+    }
+
+    #[test]
+    fn headings_and_lists_are_laid_out() {
+        let rendered = render_markdown("# News\n\nIntro\n\n- one\n- two\n\n1. first\n2. second");
+        assert_eq!(
+            rendered.text,
+            "News\n====\n\nIntro\n\n- one\n- two\n\n1. first\n2. second"
+        );
+    }
+
+    #[test]
+    fn template_tags_survive_rendering() {
+        let rendered = render_markdown(
+            "Hi {{ subscriber.name }}, [unsubscribe]({{ unsubscribe_url }}) any time.",
+        );
+        assert!(rendered.html.contains(
+            r#"<a href="{{ unsubscribe_url }}" rel="noopener noreferrer">unsubscribe</a>"#
+        ));
+        assert_eq!(
+            rendered.text,
+            "Hi {{ subscriber.name }}, unsubscribe [1] any time.\n\n[1] {{ unsubscribe_url }}"
+        );
+    }
+}
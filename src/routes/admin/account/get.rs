@@ -0,0 +1,57 @@
+use crate::{
+    auth::UserId,
+    utils::{html_escape, opaque_500_err},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn account_form(
+    flash_msg: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let email = get_user_email(**user_id, &pool)
+        .await
+        .map_err(opaque_500_err)?
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Account</title>
+    </head>
+    <body>
+        {html_msg}
+        <form action="/admin/account" method="post">
+            <label>Email address (test sends of drafts go here):<br>
+                <input type="email" name="email" value="{email}">
+            </label>
+            <br>
+            <button type="submit">Save</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#,
+            email = html_escape(&email),
+        )))
+}
+
+#[tracing::instrument(name = "Get user email", skip(pool))]
+pub async fn get_user_email(user_id: Uuid, pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let email = sqlx::query_scalar!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the user's email from db.")?;
+    Ok(email)
+}
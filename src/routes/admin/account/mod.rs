@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{account_form, get_user_email};
+pub use post::update_account;
@@ -0,0 +1,43 @@
+use crate::{
+    auth::UserId,
+    domain::SubscriberEmail,
+    utils::{opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Update account details", skip_all, fields(user_id=%*user_id))]
+pub async fn update_account(
+    form: web::Form<FormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match form.0.email.trim() {
+        "" => None,
+        email => match SubscriberEmail::parse_email(email.to_owned()) {
+            Ok(email) => Some(email),
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other("/admin/account"));
+            }
+        },
+    };
+    sqlx::query!(
+        r#"UPDATE users SET email = $2 WHERE user_id = $1"#,
+        **user_id,
+        email.as_ref().map(AsRef::<str>::as_ref)
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the user's email.")
+    .map_err(opaque_500_err)?;
+    FlashMessage::info("Your account details have been saved.").send();
+    Ok(see_other("/admin/account"))
+}
@@ -33,6 +33,16 @@ pub async fn admin_dashboard(
                 <p>Available actions:</p>
                     <ol>
                         <li><a href="/admin/password">Change password</a></li>
+                        <li><a href="/admin/account">Account</a></li>
+                        <li><a href="/admin/newsletters">Publish a newsletter issue</a></li>
+                        <li><a href="/admin/drafts">Drafts</a></li>
+                        <li><a href="/admin/layouts">Layouts</a></li>
+                        <li><a href="/admin/issues">Newsletter issues</a></li>
+                        <li><a href="/admin/lists">Mailing lists</a></li>
+                        <li><a href="/admin/subscribers">Subscribers</a></li>
+                        <li><a href="/admin/segments">Segments</a></li>
+                        <li><a href="/admin/dead_letters">Failed deliveries</a></li>
+                        <li><a href="/admin/suppressions">Suppressed addresses</a></li>
                         <li>
                             <form name = "logoutForm" action = "/admin/logout" method = "post">
                                 <input type = "submit" value = "Logout">
@@ -0,0 +1,106 @@
+use crate::utils::{html_escape, opaque_500_err};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn dead_letters(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rows = String::new();
+    for dl in get_dead_letters(&pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{email}</td>
+                <td>{n_attempts}</td>
+                <td>{failed_at}</td>
+                <td>{last_error}</td>
+                <td>
+                    <form action="/admin/dead_letters" method="post">
+                        <input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+                        <input hidden type="text" name="subscriber_email" value="{email}">
+                        <button type="submit">Re-enqueue</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = html_escape(&dl.title),
+            email = html_escape(&dl.subscriber_email),
+            n_attempts = dl.n_attempts,
+            failed_at = dl.failed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            last_error = html_escape(&dl.last_error),
+            issue_id = dl.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Failed Deliveries</title>
+    </head>
+    <body>
+        {html_msg}
+        <table>
+            <tr>
+                <th>Issue</th>
+                <th>Recipient</th>
+                <th>Attempts</th>
+                <th>Failed at</th>
+                <th>Last error</th>
+                <th></th>
+            </tr>
+            {rows}
+        </table>
+        <form action="/admin/dead_letters" method="post">
+            <button type="submit">Re-enqueue all</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#
+        )))
+}
+
+#[tracing::instrument(name = "Get dead letters", skip(pool))]
+async fn get_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            n.title,
+            d.subscriber_email,
+            d.n_attempts,
+            d.last_error,
+            d.failed_at
+        FROM issue_delivery_dead_letters d
+        JOIN newsletter_issues n USING (newsletter_issue_id)
+        ORDER BY d.failed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve dead letters from db.")?;
+    Ok(dead_letters)
+}
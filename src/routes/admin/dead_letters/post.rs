@@ -0,0 +1,74 @@
+use crate::utils::{opaque_500_err, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Both fields are left out by the "Re-enqueue all" button.
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Option<Uuid>,
+    subscriber_email: Option<String>,
+}
+
+pub async fn requeue_dead_letters(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_requeued = requeue(&pool, form.0.newsletter_issue_id, form.0.subscriber_email)
+        .await
+        .map_err(opaque_500_err)?;
+    FlashMessage::info(format!(
+        "{n_requeued} delivery task(s) have been re-enqueued."
+    ))
+    .send();
+    Ok(see_other("/admin/dead_letters"))
+}
+
+/// Moves dead letters back to the delivery queue, either a single one or all
+/// of them if no issue/recipient pair is given, and takes them out of the
+/// issues' failure counts. Cancelled issues stay out of the queue.
+#[tracing::instrument(name = "Re-enqueue dead letters", skip(pool))]
+async fn requeue(
+    pool: &PgPool,
+    newsletter_issue_id: Option<Uuid>,
+    subscriber_email: Option<String>,
+) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM issue_delivery_dead_letters
+            WHERE ($1::uuid IS NULL
+                OR (newsletter_issue_id = $1 AND subscriber_email = $2))
+                AND newsletter_issue_id NOT IN (
+                    SELECT newsletter_issue_id
+                    FROM newsletter_issues
+                    WHERE status = 'cancelled'
+                )
+            RETURNING newsletter_issue_id, subscriber_email
+        ),
+        reopened AS (
+            UPDATE newsletter_issues n
+            SET n_failed = n.n_failed - r.n_requeued
+            FROM (
+                SELECT newsletter_issue_id, count(*)::INTEGER AS n_requeued
+                FROM requeued
+                GROUP BY newsletter_issue_id
+            ) r
+            WHERE n.newsletter_issue_id = r.newsletter_issue_id
+        ),
+        enqueued AS (
+            INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+            SELECT newsletter_issue_id, subscriber_email FROM requeued
+            ON CONFLICT DO NOTHING
+            RETURNING 1
+        )
+        SELECT count(*) AS "n_requeued!" FROM enqueued
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(result.n_requeued)
+}
@@ -0,0 +1,445 @@
+use crate::{
+    issue_delivery_worker::get_layout,
+    issue_template::{IssueTemplate, Recipient, TemplateContext},
+    routes::{get_lists, get_segments, layout_options, preferences_link, unsubscribe_link},
+    startup::{ApplicationBaseUrl, HmacSecretKey, PostalAddress},
+    utils::{err_400, html_escape, opaque_500_err},
+};
+use actix_web::{
+    http::header::{ContentType, CONTENT_SECURITY_POLICY},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// An issue that has been saved but not published yet.
+pub struct Draft {
+    pub newsletter_issue_id: Uuid,
+    pub list_id: Uuid,
+    pub segment_id: Option<Uuid>,
+    pub layout_id: Option<Uuid>,
+    pub title: String,
+    pub markdown_content: Option<String>,
+    pub html_content: String,
+    pub text_content: String,
+    /// When the draft was last saved.
+    pub published_at: DateTime<Utc>,
+}
+
+struct DraftSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    list_name: String,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewQuery {
+    format: Option<String>,
+}
+
+pub async fn drafts(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let mut rows = String::new();
+    for draft in get_drafts(&pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{list_name}</td>
+                <td>{saved_at}</td>
+                <td>
+                    <a href="/admin/drafts/{id}">Edit</a>
+                    <a href="/admin/drafts/{id}/preview" target="_blank">Preview</a>
+                </td>
+            </tr>"#,
+            id = draft.newsletter_issue_id,
+            title = html_escape(&draft.title),
+            list_name = html_escape(&draft.list_name),
+            saved_at = draft.published_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Drafts</title>
+    </head>
+    <body>
+        {html_msg}
+        <p><a href="/admin/drafts/new">New draft</a></p>
+        <table>
+            <tr>
+                <th>Title</th>
+                <th>List</th>
+                <th>Last saved</th>
+                <th></th>
+            </tr>
+            {rows}
+        </table>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#
+        )))
+}
+
+pub async fn new_draft_form(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    draft_form(flash_msg, &pool, None).await
+}
+
+pub async fn edit_draft_form(
+    flash_msg: IncomingFlashMessages,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(&pool, *issue_id)
+        .await
+        .map_err(opaque_500_err)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("There is no such draft."))?;
+    draft_form(flash_msg, &pool, Some(draft)).await
+}
+
+/// The editor, along with the preview, test and publish actions once the
+/// draft has been saved.
+async fn draft_form(
+    flash_msg: IncomingFlashMessages,
+    pool: &PgPool,
+    draft: Option<Draft>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let selected = |selected: bool| if selected { " selected" } else { "" };
+    let mut list_options = String::new();
+    for list in get_lists(pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            list_options,
+            r#"<option value="{}"{}>{} ({} subscribers)</option>"#,
+            list.list_id,
+            selected(draft.as_ref().is_some_and(|d| d.list_id == list.list_id)),
+            html_escape(&list.name),
+            list.n_subscribers
+        )
+        .unwrap();
+    }
+    let mut segment_options = String::new();
+    for segment in get_segments(pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            segment_options,
+            r#"<option value="{}"{}>{}</option>"#,
+            segment.segment_id,
+            selected(
+                draft
+                    .as_ref()
+                    .is_some_and(|d| d.segment_id == Some(segment.segment_id))
+            ),
+            html_escape(&segment.name)
+        )
+        .unwrap();
+    }
+    let layout_options = layout_options(pool, draft.as_ref().and_then(|d| d.layout_id))
+        .await
+        .map_err(opaque_500_err)?;
+    let (action, title, markdown_content, text_content, html_content) = match &draft {
+        Some(draft) => {
+            let (text_content, html_content) = match draft.markdown_content {
+                // The bodies were generated, only the source is edited.
+                Some(_) => ("", ""),
+                None => (draft.text_content.as_str(), draft.html_content.as_str()),
+            };
+            (
+                format!("/admin/drafts/{}", draft.newsletter_issue_id),
+                draft.title.as_str(),
+                draft.markdown_content.as_deref().unwrap_or_default(),
+                text_content,
+                html_content,
+            )
+        }
+        None => ("/admin/drafts".to_owned(), "", "", "", ""),
+    };
+    let actions = match &draft {
+        Some(draft) => format!(
+            r#"<h2>Preview</h2>
+            <p>
+                <a href="/admin/drafts/{id}/preview" target="_blank">HTML</a>
+                <a href="/admin/drafts/{id}/preview?format=text" target="_blank">Plain text</a>
+            </p>
+            <form action="/admin/drafts/{id}/test" method="post">
+                <button type="submit">Send a test to me</button>
+            </form>
+            <p>Tests go to the address set on your <a href="/admin/account">account page</a>.</p>
+            <h2>Publish</h2>
+            <form action="/admin/drafts/{id}/publish" method="post">
+                <label>Send at (UTC, leave empty to send right away):<br>
+                    <input type="datetime-local" name="send_at">
+                </label>
+                <br>
+                <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+                <button type="submit">Publish</button>
+            </form>
+            <h2>Delete</h2>
+            <form action="/admin/drafts/{id}/delete" method="post">
+                <button type="submit">Delete this draft</button>
+            </form>"#,
+            id = draft.newsletter_issue_id,
+            idempotency_key = Uuid::new_v4(),
+        ),
+        None => String::new(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Edit Draft</title>
+    </head>
+    <body>
+        {html_msg}
+        <form action="{action}" method="post">
+            <label>List:<br>
+                <select name="list_id">
+                    {list_options}
+                </select>
+            </label>
+            <br>
+            <label>Only send to (optional):<br>
+                <select name="segment_id">
+                    <option value="">Everyone on the list</option>
+                    {segment_options}
+                </select>
+            </label>
+            <br>
+            <label>Layout:<br>
+                <select name="layout_id">
+                    {layout_options}
+                </select>
+            </label>
+            <br>
+            <label>Title:<br>
+                <input
+                    type="text"
+                    placeholder="Enter the issue title"
+                    name="title"
+                    value="{title}"
+                >
+            </label>
+            <br>
+            <label>Content (Markdown):<br>
+                <textarea
+                    placeholder="Write the issue in Markdown"
+                    name="markdown_content"
+                    rows="20"
+                    cols="50"
+                >{markdown_content}</textarea>
+            </label>
+            <br>
+            <details>
+                <summary>Or write the plain text and HTML versions yourself</summary>
+                <p>These are only used when the Markdown content is left empty.</p>
+                <label>Plain text content:<br>
+                    <textarea
+                        placeholder="Enter the content in plain text"
+                        name="text_content"
+                        rows="20"
+                        cols="50"
+                    >{text_content}</textarea>
+                </label>
+                <br>
+                <label>HTML content:<br>
+                    <textarea
+                        placeholder="Enter the content in HTML format"
+                        name="html_content"
+                        rows="20"
+                        cols="50"
+                    >{html_content}</textarea>
+                </label>
+            </details>
+            <p>The content can use <code>{{{{ subscriber.name }}}}</code>, <code>{{{{ subscriber.email }}}}</code>,
+            <code>{{{{ subscriber.attributes.<i>key</i> }}}}</code>, <code>{{{{ unsubscribe_url }}}}</code>
+            and <code>{{{{ preferences_url }}}}</code>, e.g. <code>{{{{ subscriber.attributes.city | default("there") }}}}</code>.</p>
+            <button type="submit">Save draft</button>
+        </form>
+        {actions}
+        <p><a href="/admin/drafts">&lt;- Back</a></p>
+    </body>
+    </html>"#,
+            title = html_escape(title),
+            markdown_content = html_escape(markdown_content),
+            text_content = html_escape(text_content),
+            html_content = html_escape(html_content),
+        )))
+}
+
+/// Shows the draft as a sample subscriber would receive it.
+#[tracing::instrument(
+    name = "Preview a draft",
+    skip(pool, base_url, secret, postal_address, query)
+)]
+pub async fn preview_draft(
+    issue_id: web::Path<Uuid>,
+    query: web::Query<PreviewQuery>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecretKey>,
+    postal_address: web::Data<PostalAddress>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(&pool, *issue_id)
+        .await
+        .map_err(opaque_500_err)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("There is no such draft."))?;
+    let footer = SampleFooter {
+        base_url: &base_url.0,
+        secret: &secret.0,
+        postal_address: &postal_address.0,
+    };
+    let (html, text) = render_sample(&pool, &draft, &footer)
+        .await
+        .map_err(opaque_500_err)?
+        .map_err(err_400)?;
+    Ok(match query.format.as_deref() {
+        Some("text") => HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(text),
+        _ => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            // The content is served from the admin area, so keep its scripts
+            // from running there.
+            .insert_header((CONTENT_SECURITY_POLICY, "sandbox"))
+            .body(html),
+    })
+}
+
+/// What goes into the footer of a sample message.
+pub struct SampleFooter<'a> {
+    pub base_url: &'a str,
+    pub secret: &'a Secret<String>,
+    pub postal_address: &'a str,
+}
+
+/// Renders the HTML and plain text bodies for a made-up subscriber, with
+/// every attribute known to the subscriber base filled in with its name.
+/// The outer error is unexpected, the inner one is for the editor.
+pub async fn render_sample(
+    pool: &PgPool,
+    draft: &Draft,
+    footer: &SampleFooter<'_>,
+) -> Result<Result<(String, String), String>, anyhow::Error> {
+    let layout = get_layout(pool, draft.layout_id).await?;
+    let template = IssueTemplate::new(&draft.html_content, &draft.text_content).map(|t| {
+        if draft.markdown_content.is_some() {
+            t.sanitized()
+        } else {
+            t
+        }
+    });
+    let template = match layout {
+        Some(layout) => template.and_then(|t| t.with_layout(&layout)),
+        None => template,
+    };
+    let template = match template {
+        Ok(template) => template,
+        Err(e) => return Ok(Err(e)),
+    };
+    let keys = sqlx::query_scalar!(
+        r#"SELECT DISTINCT jsonb_object_keys(attributes) AS "key!" FROM subscriptions"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber attribute names.")?;
+    let attributes = keys
+        .into_iter()
+        .map(|key| {
+            let value = format!("[{key}]");
+            (key, serde_json::Value::String(value))
+        })
+        .collect();
+    // The links look real, but don't belong to anyone.
+    let unsubscribe_url = unsubscribe_link(footer.base_url, Uuid::nil(), footer.secret);
+    let preferences_url = preferences_link(footer.base_url, Uuid::nil(), footer.secret);
+    let context = TemplateContext {
+        subscriber: Recipient {
+            name: "Ada Lovelace",
+            email: "ada@example.com",
+            attributes: &serde_json::Value::Object(attributes),
+        },
+        unsubscribe_url: &unsubscribe_url,
+        preferences_url: &preferences_url,
+        postal_address: footer.postal_address,
+    };
+    Ok(template
+        .render_email(&context)
+        .map_err(|e| format!("Failed to render the issue: {e}")))
+}
+
+#[tracing::instrument(name = "Get a draft", skip(pool))]
+pub async fn get_draft(pool: &PgPool, issue_id: Uuid) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            list_id,
+            segment_id,
+            layout_id,
+            title,
+            markdown_content,
+            html_content,
+            text_content,
+            published_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the draft from db.")?;
+    Ok(draft)
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            CASE
+                WHEN sg.name IS NULL THEN l.name
+                ELSE l.name || ' (' || sg.name || ')'
+            END AS "list_name!",
+            i.published_at
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        LEFT JOIN segments sg ON sg.segment_id = i.segment_id
+        WHERE i.status = 'draft'
+        ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve drafts from db.")?;
+    Ok(drafts)
+}
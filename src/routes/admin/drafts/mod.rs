@@ -0,0 +1,8 @@
+mod get;
+mod post;
+
+pub use get::{
+    drafts, edit_draft_form, get_draft, new_draft_form, preview_draft, render_sample, Draft,
+    SampleFooter,
+};
+pub use post::{create_draft, delete_draft, publish_draft, send_test_draft, update_draft};
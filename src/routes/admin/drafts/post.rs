@@ -0,0 +1,333 @@
+use crate::{
+    auth::UserId,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    idempotency::{save_res, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::PgTransaction,
+    issue_template::IssueTemplate,
+    routes::{
+        get_draft, get_user_email, parse_optional_id, parse_send_at, publication, render_sample,
+        start_delivery, success_message, IssueContent, SampleFooter,
+    },
+    startup::{ApplicationBaseUrl, HmacSecretKey, PostalAddress},
+    utils::{err_400, opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    list_id: Uuid,
+    /// Left empty to send to the whole list.
+    segment_id: String,
+    /// Left empty to use the default layout.
+    #[serde(default)]
+    layout_id: String,
+    title: String,
+    /// Takes precedence over the HTML and plain text bodies when filled in.
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    text_content: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishFormData {
+    /// Left empty to send the issue right away.
+    send_at: Option<String>,
+    idempotency_key: String,
+}
+
+struct DraftDetails {
+    list_id: Uuid,
+    segment_id: Option<Uuid>,
+    layout_id: Option<Uuid>,
+    title: String,
+    content: IssueContent,
+}
+
+impl TryFrom<DraftFormData> for DraftDetails {
+    type Error = uuid::Error;
+
+    fn try_from(form: DraftFormData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            list_id: form.list_id,
+            segment_id: parse_optional_id(&form.segment_id)?,
+            layout_id: parse_optional_id(&form.layout_id)?,
+            title: form.title,
+            content: IssueContent::new(form.markdown_content, form.html_content, form.text_content),
+        })
+    }
+}
+
+impl DraftDetails {
+    /// Drafts may be saved half-written, the editor is only warned.
+    fn warn_if_invalid(&self) {
+        if let Err(e) = IssueTemplate::new(&self.content.html_content, &self.content.text_content) {
+            FlashMessage::warning(format!("The draft can't be published as it is. {e}")).send();
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Create a draft",
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty)
+)]
+pub async fn create_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = DraftDetails::try_from(form.0).map_err(err_400)?;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            list_id,
+            segment_id,
+            layout_id,
+            title,
+            markdown_content,
+            html_content,
+            text_content,
+            published_at,
+            status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now(), 'draft')
+        "#,
+        issue_id,
+        draft.list_id,
+        draft.segment_id,
+        draft.layout_id,
+        draft.title,
+        draft.content.markdown_content,
+        draft.content.html_content,
+        draft.content.text_content,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the draft.")
+    .map_err(opaque_500_err)?;
+    tracing::Span::current().record("newsletter_issue_id", tracing::field::display(issue_id));
+    draft.warn_if_invalid();
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{issue_id}")))
+}
+
+#[tracing::instrument(name = "Update a draft", skip(form, pool))]
+pub async fn update_draft(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = DraftDetails::try_from(form.0).map_err(err_400)?;
+    // Until it is published, `published_at` is when the draft was last saved.
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            list_id = $2,
+            segment_id = $3,
+            layout_id = $4,
+            title = $5,
+            markdown_content = $6,
+            html_content = $7,
+            text_content = $8,
+            published_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        *issue_id,
+        draft.list_id,
+        draft.segment_id,
+        draft.layout_id,
+        draft.title,
+        draft.content.markdown_content,
+        draft.content.html_content,
+        draft.content.text_content,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the draft.")
+    .map_err(opaque_500_err)?
+    .rows_affected()
+        > 0;
+    if !updated {
+        FlashMessage::error("The draft no longer exists, it may have been published.").send();
+        return Ok(see_other("/admin/drafts"));
+    }
+    draft.warn_if_invalid();
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", *issue_id)))
+}
+
+#[tracing::instrument(name = "Delete a draft", skip(pool))]
+pub async fn delete_draft(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        *issue_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the draft.")
+    .map_err(opaque_500_err)?
+    .rows_affected()
+        > 0;
+    if deleted {
+        FlashMessage::info("The draft has been deleted.").send();
+    } else {
+        FlashMessage::error("Only drafts can be deleted.").send();
+    }
+    Ok(see_other("/admin/drafts"))
+}
+
+/// Sends the draft, as rendered for a sample subscriber, to the logged-in
+/// admin only.
+#[tracing::instrument(
+    name = "Send a test of a draft",
+    skip(pool, email_client, base_url, secret, postal_address),
+    fields(user_id=%*user_id)
+)]
+pub async fn send_test_draft(
+    issue_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecretKey>,
+    postal_address: web::Data<PostalAddress>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let editor = format!("/admin/drafts/{}", *issue_id);
+    let Some(draft) = get_draft(&pool, *issue_id).await.map_err(opaque_500_err)? else {
+        FlashMessage::error("The draft no longer exists, it may have been published.").send();
+        return Ok(see_other("/admin/drafts"));
+    };
+    let email = get_user_email(**user_id, &pool)
+        .await
+        .map_err(opaque_500_err)?
+        .map(SubscriberEmail::parse_email);
+    let email = match email {
+        Some(Ok(email)) => email,
+        _ => {
+            FlashMessage::error("Set your email address on your account page first.").send();
+            return Ok(see_other(&editor));
+        }
+    };
+    let footer = SampleFooter {
+        base_url: &base_url.0,
+        secret: &secret.0,
+        postal_address: &postal_address.0,
+    };
+    let (html, text) = match render_sample(&pool, &draft, &footer)
+        .await
+        .map_err(opaque_500_err)?
+    {
+        Ok(bodies) => bodies,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&editor));
+        }
+    };
+    let subject = format!("[Test] {}", draft.title);
+    match email_client
+        .send_email(&email, &subject, &html, &text)
+        .await
+    {
+        Ok(()) => {
+            FlashMessage::info(format!("A test has been sent to {}.", email.as_ref())).send();
+        }
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "Failed to send a test of a draft");
+            FlashMessage::error("The test could not be sent, please try again.").send();
+        }
+    }
+    Ok(see_other(&editor))
+}
+
+/// Publishes a draft through the same idempotent flow as a new issue.
+#[tracing::instrument(name = "Publish a draft", skip(form, pool), fields(user_id=%*user_id))]
+pub async fn publish_draft(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<PublishFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let PublishFormData {
+        send_at,
+        idempotency_key,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(err_400)?;
+    let send_at = parse_send_at(send_at.as_deref()).map_err(err_400)?;
+    let mut tx = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(opaque_500_err)?
+    {
+        NextAction::StartProcessing(tx) => tx,
+        NextAction::ReturnSavedResponse(resp) => {
+            success_message().send();
+            return Ok(resp);
+        }
+    };
+    let Some(draft) = get_draft(&pool, *issue_id).await.map_err(opaque_500_err)? else {
+        FlashMessage::error("The draft no longer exists, it may have been published.").send();
+        return Ok(see_other("/admin/drafts"));
+    };
+    // A broken template would otherwise only fail once queued.
+    if let Err(e) = IssueTemplate::new(&draft.html_content, &draft.text_content) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&format!("/admin/drafts/{}", *issue_id)));
+    }
+    let (published_at, status) = publication(send_at);
+    if !mark_published(&mut tx, *issue_id, published_at, status)
+        .await
+        .map_err(opaque_500_err)?
+    {
+        FlashMessage::error("The draft has already been published.").send();
+        return Ok(see_other("/admin/drafts"));
+    }
+    start_delivery(&mut tx, *issue_id, status)
+        .await
+        .map_err(opaque_500_err)?;
+
+    let resp = see_other("/admin/issues");
+    let resp = save_res(&idempotency_key, *user_id, resp, tx)
+        .await
+        .map_err(opaque_500_err)?;
+    success_message().send();
+    Ok(resp)
+}
+
+/// Returns false if the issue is no longer a draft.
+async fn mark_published(
+    tx: &mut PgTransaction,
+    issue_id: Uuid,
+    published_at: chrono::DateTime<chrono::Utc>,
+    status: &str,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $2, published_at = $3
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        issue_id,
+        status,
+        published_at
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    Ok(updated > 0)
+}
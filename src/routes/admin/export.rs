@@ -0,0 +1,286 @@
+use crate::utils::err_400;
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use futures_util::{stream, TryStreamExt};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Rows are written out in chunks of about this size.
+const CHUNK_BYTES: usize = 64 * 1024;
+const STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+/// Empty values mean "no filter", as submitted by the export form.
+#[derive(serde::Deserialize, Debug)]
+pub struct ExportParams {
+    format: ExportFormat,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    list_id: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct ExportFilters {
+    /// With a list, `status` and the date range apply to the membership
+    /// rather than to the address.
+    list_id: Option<Uuid>,
+    status: Option<String>,
+    from: Option<DateTime<Utc>>,
+    /// Exclusive.
+    until: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<&ExportParams> for ExportFilters {
+    type Error = String;
+
+    fn try_from(params: &ExportParams) -> Result<Self, Self::Error> {
+        let status = match params.status.trim() {
+            "" => None,
+            status if STATUSES.contains(&status) => Some(status.to_owned()),
+            status => return Err(format!("{status} is not a subscriber status.")),
+        };
+        let list_id = match params.list_id.trim() {
+            "" => None,
+            list_id => Some(Uuid::parse_str(list_id).map_err(|e| e.to_string())?),
+        };
+        let parse_date = |date: &str| match date.trim() {
+            "" => Ok(None),
+            date => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| format!("{date} is not a date.")),
+        };
+        let midnight = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_utc();
+        let from = parse_date(&params.from)?.map(midnight);
+        // The end date is inclusive in the form.
+        let until = parse_date(&params.to)?
+            .and_then(|to| to.succ_opt())
+            .map(midnight);
+        Ok(Self {
+            list_id,
+            status,
+            from,
+            until,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct ExportRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+    attributes: serde_json::Value,
+}
+
+impl ExportRow {
+    const CSV_HEADER: [&'static str; 7] = [
+        "id",
+        "email",
+        "name",
+        "status",
+        "subscribed_at",
+        "tags",
+        "attributes",
+    ];
+
+    fn write(&self, format: ExportFormat, out: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(out);
+                writer.write_record([
+                    &self.id.to_string(),
+                    &self.email,
+                    &self.name,
+                    &self.status,
+                    &self.subscribed_at.to_rfc3339(),
+                    &self.tags.join(","),
+                    &self.attributes.to_string(),
+                ])?;
+                writer.flush()?;
+            }
+            ExportFormat::Ndjson => {
+                serde_json::to_writer(&mut *out, self)?;
+                out.push(b'\n');
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Streams the matching subscribers straight from Postgres, without
+/// holding the whole export in memory.
+#[tracing::instrument(name = "Export subscribers", skip(pool))]
+pub async fn export_subscribers(
+    params: web::Query<ExportParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filters = ExportFilters::try_from(&*params).map_err(err_400)?;
+    let format = params.format;
+    let pool = pool.get_ref().clone();
+    // Backpressure: the query only advances as fast as the client downloads.
+    let (sender, receiver) = mpsc::channel::<Result<web::Bytes, anyhow::Error>>(4);
+    tokio::spawn(async move {
+        if let Err(e) = stream_rows(&pool, &filters, format, &sender).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to export subscribers");
+            // Makes the server abort the response, so that the client
+            // doesn't mistake a partial export for a complete one.
+            let _ = sender.send(Err(e)).await;
+        }
+    });
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+    let filename = format!("subscribers-{}.{extension}", Utc::now().format("%Y%m%d"));
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .streaming(body))
+}
+
+async fn stream_rows(
+    pool: &PgPool,
+    filters: &ExportFilters,
+    format: ExportFormat,
+    sender: &mpsc::Sender<Result<web::Bytes, anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    let mut chunk = vec![];
+    if format == ExportFormat::Csv {
+        let mut writer = csv::Writer::from_writer(&mut chunk);
+        writer.write_record(ExportRow::CSV_HEADER)?;
+        writer.flush()?;
+    }
+    let mut query = export_query(filters);
+    let mut rows = query.build_query_as::<ExportRow>().fetch(pool);
+    while let Some(row) = rows
+        .try_next()
+        .await
+        .context("Failed to fetch subscribers from db.")?
+    {
+        row.write(format, &mut chunk)?;
+        if chunk.len() >= CHUNK_BYTES {
+            let full = std::mem::replace(&mut chunk, Vec::with_capacity(CHUNK_BYTES));
+            if sender.send(Ok(full.into())).await.is_err() {
+                // The client went away.
+                return Ok(());
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        let _ = sender.send(Ok(chunk.into())).await;
+    }
+    Ok(())
+}
+
+fn export_query(filters: &ExportFilters) -> QueryBuilder<'static, Postgres> {
+    let mut query = QueryBuilder::new("SELECT s.id, s.email, s.name, s.tags, s.attributes, ");
+    let membership = match filters.list_id {
+        Some(list_id) => {
+            query
+                .push(
+                    "l.status, l.subscribed_at FROM subscriptions s \
+                    JOIN list_subscriptions l ON l.subscriber_id = s.id AND l.list_id = ",
+                )
+                .push_bind(list_id);
+            "l"
+        }
+        None => {
+            query.push("s.status, s.subscribed_at FROM subscriptions s");
+            "s"
+        }
+    };
+    query.push(" WHERE true");
+    if let Some(status) = &filters.status {
+        query
+            .push(format!(" AND {membership}.status = "))
+            .push_bind(status.clone());
+    }
+    if let Some(from) = filters.from {
+        query
+            .push(format!(" AND {membership}.subscribed_at >= "))
+            .push_bind(from);
+    }
+    if let Some(until) = filters.until {
+        query
+            .push(format!(" AND {membership}.subscribed_at < "))
+            .push_bind(until);
+    }
+    query.push(format!(" ORDER BY {membership}.subscribed_at, s.id"));
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExportFilters, ExportFormat, ExportParams};
+    use chrono::{TimeZone, Utc};
+    use claim::assert_err;
+
+    fn params(status: &str, from: &str, to: &str) -> ExportParams {
+        ExportParams {
+            format: ExportFormat::Csv,
+            status: status.into(),
+            list_id: "".into(),
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    #[test]
+    fn empty_fields_mean_no_filter() {
+        let filters = ExportFilters::try_from(&params("", "", "")).unwrap();
+        assert_eq!(
+            filters,
+            ExportFilters {
+                list_id: None,
+                status: None,
+                from: None,
+                until: None
+            }
+        );
+    }
+
+    #[test]
+    fn the_end_date_is_inclusive() {
+        let filters =
+            ExportFilters::try_from(&params("confirmed", "2024-01-01", "2024-01-31")).unwrap();
+        assert_eq!(
+            filters.from,
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(
+            filters.until,
+            Some(Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert_err!(ExportFilters::try_from(&params("deleted", "", "")));
+    }
+}
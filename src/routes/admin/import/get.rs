@@ -0,0 +1,61 @@
+use crate::{
+    routes::get_lists,
+    utils::{html_escape, opaque_500_err},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn import_form(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let mut list_options = String::new();
+    for list in get_lists(&pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            list_options,
+            r#"<option value="{}">{}</option>"#,
+            list.list_id,
+            html_escape(&list.name)
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Import Subscribers</title>
+    </head>
+    <body>
+        {html_msg}
+        <p>Upload a CSV file with a header row containing <code>email</code> and <code>name</code> columns.
+        Addresses that are already known are updated, and addresses that unsubscribed are left alone.</p>
+        <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+            <label>Add to list:
+                <select name="list_id">
+                    {list_options}
+                </select>
+            </label>
+            <br>
+            <label>
+                <input type="checkbox" name="pre_confirmed">
+                These addresses already confirmed their subscription (no confirmation email is sent)
+            </label>
+            <br>
+            <input type="file" name="file" accept=".csv,text/csv">
+            <br>
+            <button type="submit">Import</button>
+        </form>
+        <p><a href="/admin/subscribers">&lt;- Back</a></p>
+    </body>
+    </html>"#
+        )))
+}
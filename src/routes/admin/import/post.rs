@@ -0,0 +1,152 @@
+use crate::{
+    subscriber_import::{ImportError, ImportOptions, RowOutcome, RowReport, SubscriberImport},
+    utils::{err_400, html_escape, see_other},
+};
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::header::ContentType, http::StatusCode, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Upper bound for the form fields other than the file.
+const MAX_FIELD_BYTES: usize = 1024;
+
+#[tracing::instrument(name = "Import subscribers", skip_all)]
+pub async fn import_subscribers(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut list_id = None;
+    let mut pre_confirmed = false;
+    let mut report = None;
+    // The file is imported while it is being uploaded, so the other fields
+    // have to come first (browsers send them in document order).
+    while let Some(mut field) = payload.try_next().await? {
+        match field.name() {
+            Some("list_id") => {
+                let value = read_text(&mut field).await?;
+                list_id = Some(Uuid::parse_str(&value).map_err(err_400)?);
+            }
+            Some("pre_confirmed") => {
+                read_text(&mut field).await?;
+                pre_confirmed = true;
+            }
+            Some("file") => {
+                let list_id = list_id.ok_or_else(|| err_400("Pick a list before the file."))?;
+                let options = ImportOptions {
+                    list_id,
+                    pre_confirmed,
+                };
+                let mut import = SubscriberImport::new(&pool, options);
+                let result = async {
+                    while let Some(chunk) = field
+                        .try_next()
+                        .await
+                        .map_err(|e| anyhow::anyhow!("Failed to read the upload: {e}"))?
+                    {
+                        import.push(&chunk).await?;
+                    }
+                    import.finish().await
+                }
+                .await;
+                match result {
+                    Ok(()) => report = Some(import.into_report()),
+                    Err(ImportError::InvalidFile(e)) => {
+                        FlashMessage::error(e).send();
+                        return Ok(see_other("/admin/subscribers/import"));
+                    }
+                    // Rows are committed one by one, so the report is still
+                    // accurate for the rows before the failure.
+                    Err(e) => {
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            error.message = %e,
+                            "The import was interrupted",
+                        );
+                        return Ok(report_page(&import.into_report(), true));
+                    }
+                }
+            }
+            _ => while field.try_next().await?.is_some() {},
+        }
+    }
+    match report {
+        Some(report) => Ok(report_page(&report, false)),
+        None => {
+            FlashMessage::error("Choose a CSV file to import.").send();
+            Ok(see_other("/admin/subscribers/import"))
+        }
+    }
+}
+
+async fn read_text(field: &mut Field) -> Result<String, actix_web::Error> {
+    let mut value = vec![];
+    while let Some(chunk) = field.try_next().await? {
+        value.extend_from_slice(&chunk);
+        if value.len() > MAX_FIELD_BYTES {
+            return Err(err_400("A form field is too long."));
+        }
+    }
+    String::from_utf8(value).map_err(err_400)
+}
+
+fn report_page(report: &[RowReport], interrupted: bool) -> HttpResponse {
+    let count = |outcome| report.iter().filter(|row| row.outcome == outcome).count();
+    let mut rows = String::new();
+    for row in report {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{line}</td>
+                <td>{email}</td>
+                <td>{outcome:?}</td>
+                <td>{reason}</td>
+            </tr>"#,
+            line = row.line,
+            email = html_escape(&row.email),
+            outcome = row.outcome,
+            reason = html_escape(&row.reason),
+        )
+        .unwrap();
+    }
+    let (status, interruption) = if interrupted {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "<p><i>The import was interrupted by an unexpected error. \
+            The rows below were imported, run it again to import the rest.</i></p>",
+        )
+    } else {
+        (StatusCode::OK, "")
+    };
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Import Report</title>
+    </head>
+    <body>
+        {interruption}
+        <p>{accepted} accepted, {skipped} skipped, {rejected} rejected.</p>
+        <table>
+            <tr>
+                <th>Line</th>
+                <th>Email</th>
+                <th>Outcome</th>
+                <th>Reason</th>
+            </tr>
+            {rows}
+        </table>
+        <p><a href="/admin/subscribers/import">Import another file</a></p>
+        <p><a href="/admin/subscribers">&lt;- Back</a></p>
+    </body>
+    </html>"#,
+            accepted = count(RowOutcome::Accepted),
+            skipped = count(RowOutcome::Skipped),
+            rejected = count(RowOutcome::Rejected),
+        ))
+}
@@ -0,0 +1,163 @@
+use crate::utils::{html_escape, opaque_500_err};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct IssueProgress {
+    newsletter_issue_id: Uuid,
+    list_name: String,
+    title: String,
+    published_at: DateTime<Utc>,
+    n_total: i32,
+    n_sent: i32,
+    n_failed: i32,
+    status: String,
+}
+
+impl IssueProgress {
+    fn n_pending(&self) -> i32 {
+        self.n_total - self.n_sent - self.n_failed
+    }
+
+    fn is_in_flight(&self) -> bool {
+        self.status == "sending" && self.n_pending() > 0
+    }
+
+    fn status(&self) -> &'static str {
+        match self.status.as_str() {
+            "scheduled" => "Scheduled",
+            "paused" => "Paused",
+            "cancelled" => "Cancelled",
+            _ if self.n_pending() > 0 => "Sending",
+            _ => "Completed",
+        }
+    }
+
+    fn actions(&self) -> String {
+        let action = |name: &str, label: &str| {
+            format!(
+                r#"<form action="/admin/issues/{}/{name}" method="post"><button type="submit">{label}</button></form>"#,
+                self.newsletter_issue_id
+            )
+        };
+        match self.status() {
+            "Scheduled" => {
+                format!(
+                    r#"<form action="/admin/issues/{}/reschedule" method="post"><label>Send at (UTC): <input type="datetime-local" name="send_at" value="{}"></label><button type="submit">Reschedule</button></form>"#,
+                    self.newsletter_issue_id,
+                    self.published_at.format("%Y-%m-%dT%H:%M")
+                ) + &action("cancel", "Cancel")
+            }
+            "Sending" => action("pause", "Pause") + &action("cancel", "Cancel"),
+            "Paused" => action("resume", "Resume") + &action("cancel", "Cancel"),
+            _ => String::new(),
+        }
+    }
+}
+
+pub async fn list_issues(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let issues = get_issues(&pool).await.map_err(opaque_500_err)?;
+    // Keep refreshing while anything is still going out.
+    let refresh = if issues.iter().any(|issue| issue.is_in_flight()) {
+        r#"<meta http-equiv="refresh" content="5">"#
+    } else {
+        ""
+    };
+    let mut rows = String::new();
+    for issue in &issues {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{list_name}</td>
+                <td>{published_at}</td>
+                <td>{status}</td>
+                <td>{n_sent} / {n_total}</td>
+                <td>{n_failed}</td>
+                <td>{n_pending}</td>
+                <td>{actions}</td>
+            </tr>"#,
+            title = html_escape(&issue.title),
+            list_name = html_escape(&issue.list_name),
+            published_at = issue.published_at.format("%Y-%m-%d %H:%M UTC"),
+            status = issue.status(),
+            n_sent = issue.n_sent,
+            n_total = issue.n_total,
+            n_failed = issue.n_failed,
+            n_pending = issue.n_pending(),
+            actions = issue.actions(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        {refresh}
+        <title>Newsletter Issues</title>
+    </head>
+    <body>
+        {html_msg}
+        <table>
+            <tr>
+                <th>Title</th>
+                <th>List</th>
+                <th>Published at</th>
+                <th>Status</th>
+                <th>Sent</th>
+                <th>Failed</th>
+                <th>Pending</th>
+                <th></th>
+            </tr>
+            {rows}
+        </table>
+        <p><a href="/admin/dead_letters">Failed deliveries</a></p>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#
+        )))
+}
+
+#[tracing::instrument(name = "Get newsletter issues", skip(pool))]
+async fn get_issues(pool: &PgPool) -> Result<Vec<IssueProgress>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueProgress,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            CASE
+                WHEN sg.name IS NULL THEN l.name
+                ELSE l.name || ' (' || sg.name || ')'
+            END AS "list_name!",
+            i.title,
+            i.published_at,
+            i.n_total,
+            i.n_sent,
+            i.n_failed,
+            i.status
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        LEFT JOIN segments sg ON sg.segment_id = i.segment_id
+        WHERE i.status <> 'draft'
+        ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve newsletter issues from db.")?;
+    Ok(issues)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::list_issues;
+pub use post::{cancel_issue, pause_issue, reschedule_issue, resume_issue};
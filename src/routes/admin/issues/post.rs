@@ -0,0 +1,152 @@
+use crate::{
+    issue_delivery_worker::{notify_workers, PgTransaction},
+    routes::parse_send_at,
+    utils::{err_400, opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(name = "Pause a newsletter issue", skip(pool))]
+pub async fn pause_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut tx = pool.begin().await.map_err(opaque_500_err)?;
+    let paused = transition(&mut tx, *issue_id, &["sending"], "paused")
+        .await
+        .map_err(opaque_500_err)?;
+    tx.commit().await.map_err(opaque_500_err)?;
+    if paused {
+        FlashMessage::info("Delivery has been paused.").send();
+    } else {
+        FlashMessage::error("Only issues that are being sent can be paused.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[tracing::instrument(name = "Resume a newsletter issue", skip(pool))]
+pub async fn resume_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut tx = pool.begin().await.map_err(opaque_500_err)?;
+    let resumed = transition(&mut tx, *issue_id, &["paused"], "sending")
+        .await
+        .map_err(opaque_500_err)?;
+    if resumed {
+        // Workers may be idling, since they skipped this issue's tasks so far.
+        notify_workers(&mut tx, *issue_id)
+            .await
+            .map_err(opaque_500_err)?;
+    }
+    tx.commit().await.map_err(opaque_500_err)?;
+    if resumed {
+        FlashMessage::info("Delivery has been resumed.").send();
+    } else {
+        FlashMessage::error("Only paused issues can be resumed.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[tracing::instrument(name = "Cancel a newsletter issue", skip(pool))]
+pub async fn cancel_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut tx = pool.begin().await.map_err(opaque_500_err)?;
+    let cancelled = transition(
+        &mut tx,
+        *issue_id,
+        &["scheduled", "sending", "paused"],
+        "cancelled",
+    )
+    .await
+    .map_err(opaque_500_err)?;
+    if cancelled {
+        sqlx::query!(
+            r#"
+            DELETE FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1
+            "#,
+            *issue_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(opaque_500_err)?;
+    }
+    tx.commit().await.map_err(opaque_500_err)?;
+    if cancelled {
+        FlashMessage::info("Delivery has been cancelled.").send();
+    } else {
+        FlashMessage::error("This issue can no longer be cancelled.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    send_at: String,
+}
+
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(form, pool))]
+pub async fn reschedule_issue(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let send_at = parse_send_at(Some(&form.send_at)).map_err(err_400)?;
+    let mut tx = pool.begin().await.map_err(opaque_500_err)?;
+    // Clearing the send time sends it right away.
+    let rescheduled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = COALESCE($2, now())
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        *issue_id,
+        send_at
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(opaque_500_err)?
+    .rows_affected()
+        == 1;
+    if rescheduled {
+        notify_workers(&mut tx, *issue_id)
+            .await
+            .map_err(opaque_500_err)?;
+    }
+    tx.commit().await.map_err(opaque_500_err)?;
+    if rescheduled {
+        FlashMessage::info("The issue has been rescheduled.").send();
+    } else {
+        FlashMessage::error("Only scheduled issues can be rescheduled.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+/// Moves an issue to status `to` if it currently is in one of the `from`
+/// states. Returns whether it did.
+async fn transition(
+    tx: &mut PgTransaction,
+    issue_id: Uuid,
+    from: &[&str],
+    to: &str,
+) -> Result<bool, sqlx::Error> {
+    let from: Vec<String> = from.iter().map(|s| s.to_string()).collect();
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $3
+        WHERE newsletter_issue_id = $1 AND status = ANY($2)
+        "#,
+        issue_id,
+        &from,
+        to
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
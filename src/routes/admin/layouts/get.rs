@@ -0,0 +1,213 @@
+use crate::utils::{html_escape, opaque_500_err};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub struct Layout {
+    pub layout_id: Uuid,
+    pub name: String,
+    pub html: String,
+    pub is_default: bool,
+}
+
+const EXAMPLE: &str = r#"<html>
+<head><style>body { font-family: sans-serif; }</style></head>
+<body>
+  <header><img src="https://example.com/logo.png" alt="Our newsletter"></header>
+  {{ content }}
+</body>
+</html>"#;
+
+pub async fn layouts(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let layouts = get_layouts(&pool).await.map_err(opaque_500_err)?;
+    let mut rows = String::new();
+    for layout in &layouts {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{default}</td>
+                <td><a href="/admin/layouts/{id}">Edit</a></td>
+            </tr>"#,
+            id = layout.layout_id,
+            name = html_escape(&layout.name),
+            default = if layout.is_default { "Yes" } else { "" },
+        )
+        .unwrap();
+    }
+    let mut default_options = String::new();
+    for layout in &layouts {
+        writeln!(
+            default_options,
+            r#"<option value="{}"{}>{}</option>"#,
+            layout.layout_id,
+            if layout.is_default { " selected" } else { "" },
+            html_escape(&layout.name)
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Layouts</title>
+    </head>
+    <body>
+        {html_msg}
+        <table>
+            <tr>
+                <th>Name</th>
+                <th>Default</th>
+                <th></th>
+            </tr>
+            {rows}
+        </table>
+        <form action="/admin/layouts/default" method="post">
+            <label>Issues use by default:
+                <select name="layout_id">
+                    <option value="">No layout</option>
+                    {default_options}
+                </select>
+            </label>
+            <button type="submit">Save</button>
+        </form>
+        <h2>New layout</h2>
+        <form action="/admin/layouts" method="post">
+            <label>Name:<br>
+                <input type="text" name="name" placeholder="Branded">
+            </label>
+            <br>
+            <label>HTML:<br>
+                <textarea name="html" rows="20" cols="80">{example}</textarea>
+            </label>
+            <br>
+            <button type="submit">Create layout</button>
+        </form>
+        {help}
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#,
+            example = html_escape(EXAMPLE),
+            help = help(),
+        )))
+}
+
+pub async fn edit_layout_form(
+    flash_msg: IncomingFlashMessages,
+    layout_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let layout = sqlx::query_as!(
+        Layout,
+        r#"
+        SELECT layout_id, name, html, is_default
+        FROM layouts
+        WHERE layout_id = $1
+        "#,
+        *layout_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(opaque_500_err)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("There is no such layout."))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Edit Layout</title>
+    </head>
+    <body>
+        {html_msg}
+        <form action="/admin/layouts/{id}" method="post">
+            <label>Name:<br>
+                <input type="text" name="name" value="{name}">
+            </label>
+            <br>
+            <label>HTML:<br>
+                <textarea name="html" rows="20" cols="80">{html}</textarea>
+            </label>
+            <br>
+            <button type="submit">Save layout</button>
+        </form>
+        {help}
+        <form action="/admin/layouts/{id}/delete" method="post">
+            <p>Issues using this layout will use the default one instead.</p>
+            <button type="submit">Delete this layout</button>
+        </form>
+        <p><a href="/admin/layouts">&lt;- Back</a></p>
+    </body>
+    </html>"#,
+            id = layout.layout_id,
+            name = html_escape(&layout.name),
+            html = html_escape(&layout.html),
+            help = help(),
+        )))
+}
+
+fn help() -> &'static str {
+    r#"<p>The issue, followed by the postal address and the unsubscribe link, goes where the
+        layout shows <code>{{ content }}</code>. Layouts can also use <code>{{ subscriber.name }}</code>,
+        <code>{{ subscriber.email }}</code>, <code>{{ subscriber.attributes.<i>key</i> }}</code>,
+        <code>{{ unsubscribe_url }}</code>, <code>{{ preferences_url }}</code>
+        and <code>{{ postal_address }}</code>.</p>"#
+}
+
+/// The `<option>`s of a layout choice, the first one standing for the
+/// default layout.
+pub async fn layout_options(
+    pool: &PgPool,
+    selected: Option<Uuid>,
+) -> Result<String, anyhow::Error> {
+    let mut options = String::from(r#"<option value="">Default layout</option>"#);
+    for layout in get_layouts(pool).await? {
+        write!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            layout.layout_id,
+            if selected == Some(layout.layout_id) {
+                " selected"
+            } else {
+                ""
+            },
+            html_escape(&layout.name)
+        )
+        .unwrap();
+    }
+    Ok(options)
+}
+
+#[tracing::instrument(name = "Get layouts", skip(pool))]
+pub async fn get_layouts(pool: &PgPool) -> Result<Vec<Layout>, anyhow::Error> {
+    let layouts = sqlx::query_as!(
+        Layout,
+        r#"
+        SELECT layout_id, name, html, is_default
+        FROM layouts
+        ORDER BY name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve layouts from db.")?;
+    Ok(layouts)
+}
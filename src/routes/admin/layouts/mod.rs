@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{edit_layout_form, layout_options, layouts};
+pub use post::{create_layout, delete_layout, set_default_layout, update_layout};
@@ -0,0 +1,159 @@
+use crate::{
+    issue_template::IssueTemplate,
+    routes::parse_optional_id,
+    utils::{err_400, opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    html: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DefaultFormData {
+    /// Left empty for issues not to use a layout by default.
+    layout_id: String,
+}
+
+impl FormData {
+    /// Layouts apply to issues being sent, so they are only saved if valid.
+    fn validate(&self) -> Result<&str, String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err("A layout needs a name.".into());
+        }
+        IssueTemplate::check_layout(&self.html)?;
+        Ok(name)
+    }
+}
+
+#[tracing::instrument(name = "Create a layout", skip(form, pool), fields(name = %form.name))]
+pub async fn create_layout(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match form.validate() {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/layouts"));
+        }
+    };
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO layouts (layout_id, name, html)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+        form.html
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the layout.")
+    .map_err(opaque_500_err)?
+    .rows_affected();
+    if n_inserted == 1 {
+        FlashMessage::info(format!("The {name} layout has been created.")).send();
+    } else {
+        FlashMessage::error(format!("There already is a {name} layout.")).send();
+    }
+    Ok(see_other("/admin/layouts"))
+}
+
+#[tracing::instrument(name = "Update a layout", skip(form, pool), fields(name = %form.name))]
+pub async fn update_layout(
+    layout_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let editor = format!("/admin/layouts/{}", *layout_id);
+    let name = match form.validate() {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&editor));
+        }
+    };
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE layouts
+        SET name = $2, html = $3
+        WHERE layout_id = $1
+            AND NOT EXISTS (SELECT 1 FROM layouts WHERE name = $2 AND layout_id <> $1)
+        "#,
+        *layout_id,
+        name,
+        form.html
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the layout.")
+    .map_err(opaque_500_err)?
+    .rows_affected();
+    if n_updated == 1 {
+        FlashMessage::info(format!("The {name} layout has been saved.")).send();
+        return Ok(see_other(&editor));
+    }
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM layouts WHERE layout_id = $1) AS "exists!""#,
+        *layout_id
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to look the layout up.")
+    .map_err(opaque_500_err)?;
+    if exists {
+        FlashMessage::error(format!("There already is a {name} layout.")).send();
+        Ok(see_other(&editor))
+    } else {
+        FlashMessage::error("The layout no longer exists, it may have been deleted.").send();
+        Ok(see_other("/admin/layouts"))
+    }
+}
+
+#[tracing::instrument(name = "Delete a layout", skip(pool))]
+pub async fn delete_layout(
+    layout_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    sqlx::query!(r#"DELETE FROM layouts WHERE layout_id = $1"#, *layout_id)
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to delete the layout.")
+        .map_err(opaque_500_err)?;
+    FlashMessage::info("The layout has been deleted.").send();
+    Ok(see_other("/admin/layouts"))
+}
+
+#[tracing::instrument(name = "Set the default layout", skip_all)]
+pub async fn set_default_layout(
+    form: web::Form<DefaultFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout_id = parse_optional_id(&form.layout_id).map_err(err_400)?;
+    let mut tx = pool.begin().await.map_err(opaque_500_err)?;
+    sqlx::query!(r#"UPDATE layouts SET is_default = false WHERE is_default"#)
+        .execute(&mut *tx)
+        .await
+        .map_err(opaque_500_err)?;
+    if let Some(layout_id) = layout_id {
+        sqlx::query!(
+            r#"UPDATE layouts SET is_default = true WHERE layout_id = $1"#,
+            layout_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(opaque_500_err)?;
+    }
+    tx.commit().await.map_err(opaque_500_err)?;
+    FlashMessage::info("The default layout has been saved.").send();
+    Ok(see_other("/admin/layouts"))
+}
@@ -0,0 +1,94 @@
+use crate::utils::{html_escape, opaque_500_err};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub n_subscribers: i64,
+}
+
+pub async fn lists(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let mut rows = String::new();
+    for list in get_lists(&pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{slug}</td>
+                <td>{n_subscribers}</td>
+            </tr>"#,
+            name = html_escape(&list.name),
+            slug = html_escape(&list.slug),
+            n_subscribers = list.n_subscribers,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Mailing Lists</title>
+    </head>
+    <body>
+        {html_msg}
+        <table>
+            <tr>
+                <th>Name</th>
+                <th>Identifier</th>
+                <th>Confirmed subscribers</th>
+            </tr>
+            {rows}
+        </table>
+        <form action="/admin/lists" method="post">
+            <label>Name:
+                <input type="text" name="name" placeholder="Weekly digest">
+            </label>
+            <label>Identifier:
+                <input type="text" name="slug" placeholder="weekly-digest">
+            </label>
+            <button type="submit">Create list</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#
+        )))
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT
+            l.list_id,
+            l.slug,
+            l.name,
+            count(s.id) AS "n_subscribers!"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls ON ls.list_id = l.list_id AND ls.status = 'confirmed'
+        LEFT JOIN subscriptions s ON s.id = ls.subscriber_id AND s.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve mailing lists from db.")?;
+    Ok(lists)
+}
@@ -0,0 +1,54 @@
+use crate::{
+    domain::ListSlug,
+    utils::{opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    slug: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool), fields(slug = %form.slug))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { name, slug } = form.0;
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("A list needs a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    let slug = match ListSlug::parse(slug) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(opaque_500_err)?
+    .rows_affected();
+    if n_inserted == 1 {
+        FlashMessage::info(format!("The {name} list has been created.")).send();
+    } else {
+        FlashMessage::error(format!("There already is a {} list.", slug.as_ref())).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -1,9 +1,31 @@
+mod account;
 mod dashboard;
+mod dead_letters;
+mod drafts;
+mod export;
+mod import;
+mod issues;
+mod layouts;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod segments;
+mod subscribers;
+mod suppressions;
 
+pub use account::*;
 pub use dashboard::*;
+pub use dead_letters::*;
+pub use drafts::*;
+pub use export::export_subscribers;
+pub use import::*;
+pub use issues::*;
+pub use layouts::*;
+pub use lists::*;
 pub use logout::logout;
 pub use newsletter::*;
 pub use password::*;
+pub use segments::*;
+pub use subscribers::*;
+pub use suppressions::*;
@@ -1,14 +1,42 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use crate::{
+    routes::{get_lists, get_segments, layout_options},
+    utils::{html_escape, opaque_500_err},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use std::fmt::Write;
 
 pub async fn publish_newsletter_form(
     flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut html_msg = String::new();
     for m in flash_msg.iter() {
         writeln!(html_msg, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let mut list_options = String::new();
+    for list in get_lists(&pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            list_options,
+            r#"<option value="{}">{} ({} subscribers)</option>"#,
+            list.list_id,
+            html_escape(&list.name),
+            list.n_subscribers
+        )
+        .unwrap();
+    }
+    let mut segment_options = String::new();
+    for segment in get_segments(&pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            segment_options,
+            r#"<option value="{}">{}</option>"#,
+            segment.segment_id,
+            html_escape(&segment.name)
+        )
+        .unwrap();
+    }
+    let layout_options = layout_options(&pool, None).await.map_err(opaque_500_err)?;
     let idempotency_key = uuid::Uuid::new_v4();
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -22,6 +50,25 @@ pub async fn publish_newsletter_form(
     <body>
         {html_msg}
         <form action="/admin/newsletters" method="post">
+            <label>List:<br>
+                <select name="list_id">
+                    {list_options}
+                </select>
+            </label>
+            <br>
+            <label>Only send to (optional):<br>
+                <select name="segment_id">
+                    <option value="">Everyone on the list</option>
+                    {segment_options}
+                </select>
+            </label>
+            <br>
+            <label>Layout:<br>
+                <select name="layout_id">
+                    {layout_options}
+                </select>
+            </label>
+            <br>
             <label>Title:<br>
                 <input
                     type="text"
@@ -30,22 +77,41 @@ pub async fn publish_newsletter_form(
                 >
             </label>
             <br>
-            <label>Plain text content:<br>
+            <label>Content (Markdown):<br>
                 <textarea
-                    placeholder="Enter the content in plain text"
-                    name="text_content"
+                    placeholder="Write the issue in Markdown"
+                    name="markdown_content"
                     rows="20"
                     cols="50"
                 ></textarea>
             </label>
             <br>
-            <label>HTML content:<br>
-                <textarea
-                    placeholder="Enter the content in HTML format"
-                    name="html_content"
-                    rows="20"
-                    cols="50"
-                ></textarea>
+            <details>
+                <summary>Or write the plain text and HTML versions yourself</summary>
+                <p>These are only used when the Markdown content is left empty.</p>
+                <label>Plain text content:<br>
+                    <textarea
+                        placeholder="Enter the content in plain text"
+                        name="text_content"
+                        rows="20"
+                        cols="50"
+                    ></textarea>
+                </label>
+                <br>
+                <label>HTML content:<br>
+                    <textarea
+                        placeholder="Enter the content in HTML format"
+                        name="html_content"
+                        rows="20"
+                        cols="50"
+                    ></textarea>
+                </label>
+            </details>
+            <p>The content can use <code>{{{{ subscriber.name }}}}</code>, <code>{{{{ subscriber.email }}}}</code>,
+            <code>{{{{ subscriber.attributes.<i>key</i> }}}}</code>, <code>{{{{ unsubscribe_url }}}}</code>
+            and <code>{{{{ preferences_url }}}}</code>, e.g. <code>{{{{ subscriber.attributes.city | default("there") }}}}</code>.</p>
+            <label>Send at (UTC, leave empty to send right away):<br>
+                <input type="datetime-local" name="send_at">
             </label>
             <br>
             <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
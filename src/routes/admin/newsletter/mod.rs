@@ -2,4 +2,7 @@ mod get;
 mod post;
 
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub use post::{
+    parse_optional_id, parse_send_at, publication, publish_newsletter, start_delivery,
+    success_message, IssueContent,
+};
@@ -2,11 +2,15 @@ use crate::{
     auth::UserId,
     domain::SubscriberEmail,
     idempotency::{save_res, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::{enqueue_delivery_tasks, notify_workers, PgTransaction},
+    issue_template::IssueTemplate,
+    markdown::render_markdown,
     utils::{err_400, opaque_500_err, see_other},
 };
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::{Context, Ok};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use sqlx::{PgPool, Transaction};
 use uuid::Uuid;
 
@@ -16,9 +20,22 @@ use core::result::Result::Ok as core_Ok;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
+    list_id: Uuid,
+    /// Left empty to send to the whole list.
+    segment_id: String,
+    /// Left empty to use the default layout.
+    #[serde(default)]
+    layout_id: String,
     title: String,
+    /// Takes precedence over the HTML and plain text bodies when filled in.
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
     html_content: String,
+    #[serde(default)]
     text_content: String,
+    /// Left empty to send the issue right away.
+    send_at: Option<String>,
     idempotency_key: String,
 }
 
@@ -31,7 +48,7 @@ struct ConfirmedSubscriber {
 #[tracing::instrument(
     name = "Publish a newsletter issue",
     skip_all,
-    fields(user_id=%*user_id)
+    fields(user_id=%*user_id, newsletter_issue_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
@@ -40,12 +57,23 @@ pub async fn publish_newsletter(
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let FormData {
+        list_id,
+        segment_id,
+        layout_id,
         title,
+        markdown_content,
         text_content,
         html_content,
+        send_at,
         idempotency_key,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(err_400)?;
+    let send_at = parse_send_at(send_at.as_deref()).map_err(err_400)?;
+    let segment_id = parse_optional_id(&segment_id).map_err(err_400)?;
+    let layout_id = parse_optional_id(&layout_id).map_err(err_400)?;
+    let content = IssueContent::new(markdown_content, html_content, text_content);
+    // A broken template would otherwise only fail once queued.
+    IssueTemplate::new(&content.html_content, &content.text_content).map_err(err_400)?;
     let mut tx = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(opaque_500_err)?
@@ -57,16 +85,26 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut tx, &title, &text_content, &html_content)
+    let (published_at, status) = publication(send_at);
+    let new_issue = NewIssue {
+        list_id,
+        segment_id,
+        layout_id,
+        title: &title,
+        content: &content,
+        published_at,
+        status,
+    };
+    let issue_id = insert_newsletter_issue(&mut tx, &new_issue)
         .await
         .context("Failed to store newsletter issue details")
         .map_err(opaque_500_err)?;
-    enqueue_delivery_tasks(&mut tx, issue_id)
+    start_delivery(&mut tx, issue_id, status)
         .await
-        .context("Failed to enqueue delivery tasks")
         .map_err(opaque_500_err)?;
+    tracing::Span::current().record("newsletter_issue_id", tracing::field::display(issue_id));
 
-    let resp = see_other("/admin/newsletters");
+    let resp = see_other("/admin/issues");
     let resp = save_res(&idempotency_key, *user_id, resp, tx)
         .await
         .map_err(opaque_500_err)?;
@@ -96,48 +134,134 @@ pub async fn get_confirmed_subscribers(
     Ok(confirmed_subscribers)
 }
 
-fn success_message() -> FlashMessage {
+pub fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted and will shortly be published!")
 }
 
+/// The bodies of an issue, as submitted by editors.
+pub struct IssueContent {
+    pub markdown_content: Option<String>,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+impl IssueContent {
+    /// Both bodies are generated from the Markdown source when there is one.
+    pub fn new(markdown_content: String, html_content: String, text_content: String) -> Self {
+        match Some(markdown_content).filter(|m| !m.trim().is_empty()) {
+            Some(markdown) => {
+                let rendered = render_markdown(&markdown);
+                Self {
+                    markdown_content: Some(markdown),
+                    html_content: rendered.html,
+                    text_content: rendered.text,
+                }
+            }
+            None => Self {
+                markdown_content: None,
+                html_content,
+                text_content,
+            },
+        }
+    }
+}
+
+/// When the issue counts as published and the status it starts in.
+pub fn publication(send_at: Option<DateTime<Utc>>) -> (DateTime<Utc>, &'static str) {
+    match send_at {
+        Some(send_at) => (send_at, "scheduled"),
+        None => (Utc::now(), "sending"),
+    }
+}
+
+/// Queues an issue that was just published, or lets the scheduler know
+/// about it.
+pub async fn start_delivery(
+    tx: &mut PgTransaction,
+    issue_id: Uuid,
+    status: &str,
+) -> Result<(), anyhow::Error> {
+    if status == "scheduled" {
+        // The scheduler may have to wake up earlier than it planned to.
+        notify_workers(tx, issue_id)
+            .await
+            .context("Failed to notify the delivery workers")?;
+    } else {
+        enqueue_delivery_tasks(tx, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+    Ok(())
+}
+
+struct NewIssue<'a> {
+    list_id: Uuid,
+    segment_id: Option<Uuid>,
+    layout_id: Option<Uuid>,
+    title: &'a str,
+    content: &'a IssueContent,
+    published_at: DateTime<Utc>,
+    status: &'a str,
+}
+
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, sqlx::Postgres>,
-    title: &str,
-    html_content: &str,
-    text_content: &str,
+    issue: &NewIssue<'_>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-        INSERT INTO newsletter_issues (newsletter_issue_id, title, html_content, text_content, published_at)
-        VALUES ($1, $2, $3, $4, now())
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            list_id,
+            segment_id,
+            layout_id,
+            title,
+            markdown_content,
+            html_content,
+            text_content,
+            published_at,
+            status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
         "#,
         newsletter_issue_id,
-        title,
-        html_content,
-        text_content
+        issue.list_id,
+        issue.segment_id,
+        issue.layout_id,
+        issue.title,
+        issue.content.markdown_content,
+        issue.content.html_content,
+        issue.content.text_content,
+        issue.published_at,
+        issue.status
     )
     .execute(&mut **transaction)
     .await?;
     sqlx::error::Result::Ok(newsletter_issue_id)
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, sqlx::Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id,
-    )
-    .execute(&mut **transaction)
-    .await?;
-    anyhow_Result::Ok(())
+/// Parses the value of an optional choice, where empty means none.
+pub fn parse_optional_id(id: &str) -> Result<Option<Uuid>, uuid::Error> {
+    match id.trim() {
+        "" => core_Ok(None),
+        id => Uuid::parse_str(id).map(Some),
+    }
+}
+
+/// Parses the `datetime-local` value of the publish form, which is in UTC.
+/// Empty values mean "send it now", times in the past are refused.
+pub fn parse_send_at(send_at: Option<&str>) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let send_at = match send_at.map(str::trim) {
+        None | Some("") => return Ok(None),
+        Some(send_at) => send_at,
+    };
+    let send_at = NaiveDateTime::parse_from_str(send_at, "%Y-%m-%dT%H:%M")
+        .with_context(|| format!("{send_at} is not a valid send time."))?
+        .and_utc();
+    if send_at <= Utc::now() {
+        anyhow::bail!("The send time has to be in the future.");
+    }
+    Ok(Some(send_at))
 }
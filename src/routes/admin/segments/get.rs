@@ -0,0 +1,111 @@
+use crate::{
+    domain::SegmentFilter,
+    utils::{html_escape, opaque_500_err},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::{PgPool, QueryBuilder};
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub filter: String,
+}
+
+pub async fn segments(
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let mut rows = String::new();
+    for segment in get_segments(&pool).await.map_err(opaque_500_err)? {
+        let n_matching = count_matching(&pool, &segment.filter)
+            .await
+            .map_err(opaque_500_err)?;
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{name}</td>
+                <td><code>{filter}</code></td>
+                <td>{n_matching}</td>
+            </tr>"#,
+            name = html_escape(&segment.name),
+            filter = html_escape(&segment.filter),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Segments</title>
+    </head>
+    <body>
+        {html_msg}
+        <table>
+            <tr>
+                <th>Name</th>
+                <th>Filter</th>
+                <th>Confirmed subscribers</th>
+            </tr>
+            {rows}
+        </table>
+        <form action="/admin/segments" method="post">
+            <label>Name:
+                <input type="text" name="name" placeholder="EMEA customers">
+            </label>
+            <label>Filter:
+                <input type="text" name="filter" size="60" placeholder="tag:customer and attr:region=emea">
+            </label>
+            <button type="submit">Create segment</button>
+        </form>
+        <p>
+            Filters combine <code>tag:NAME</code>, <code>attr:KEY=VALUE</code>,
+            <code>subscribed_before:YYYY-MM-DD</code> and <code>subscribed_after:YYYY-MM-DD</code>
+            with <code>and</code>; any condition can be preceded by <code>not</code>.
+            Quote values containing spaces.
+        </p>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#
+        )))
+}
+
+#[tracing::instrument(name = "Get segments", skip(pool))]
+pub async fn get_segments(pool: &PgPool) -> Result<Vec<Segment>, anyhow::Error> {
+    let segments = sqlx::query_as!(
+        Segment,
+        r#"
+        SELECT segment_id, name, filter
+        FROM segments
+        ORDER BY name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve segments from db.")?;
+    Ok(segments)
+}
+
+#[tracing::instrument(name = "Count subscribers matching a segment", skip(pool))]
+async fn count_matching(pool: &PgPool, filter: &str) -> Result<i64, anyhow::Error> {
+    let filter = SegmentFilter::parse(filter).map_err(anyhow::Error::msg)?;
+    let mut query =
+        QueryBuilder::new("SELECT count(*) FROM subscriptions s WHERE s.status = 'confirmed'");
+    filter.push_conditions(&mut query);
+    let n_matching = query
+        .build_query_scalar()
+        .fetch_one(pool)
+        .await
+        .context("Failed to count the subscribers matching a segment.")?;
+    Ok(n_matching)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{get_segments, segments, Segment};
+pub use post::create_segment;
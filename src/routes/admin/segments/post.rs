@@ -0,0 +1,52 @@
+use crate::{
+    domain::SegmentFilter,
+    utils::{opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    filter: String,
+}
+
+#[tracing::instrument(name = "Create a segment", skip(form, pool), fields(name = %form.name))]
+pub async fn create_segment(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { name, filter } = form.0;
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("A segment needs a name.").send();
+        return Ok(see_other("/admin/segments"));
+    }
+    // Stored as written, but only once we know it parses.
+    if let Err(e) = SegmentFilter::parse(&filter) {
+        FlashMessage::error(format!("Invalid filter: {e}")).send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, filter)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+        filter.trim()
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(opaque_500_err)?
+    .rows_affected();
+    if n_inserted == 1 {
+        FlashMessage::info(format!("The {name} segment has been created.")).send();
+    } else {
+        FlashMessage::error(format!("There already is a {name} segment.")).send();
+    }
+    Ok(see_other("/admin/segments"))
+}
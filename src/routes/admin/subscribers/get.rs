@@ -0,0 +1,202 @@
+use crate::{
+    routes::{data_download, get_lists, get_subscriber_data},
+    utils::{html_escape, opaque_500_err},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataQueryParams {
+    email: String,
+}
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    tags: Vec<String>,
+    attributes: serde_json::Value,
+}
+
+impl Subscriber {
+    /// One `key=value` per line, as edited in the form.
+    fn attribute_lines(&self) -> String {
+        let mut lines = String::new();
+        if let Some(attributes) = self.attributes.as_object() {
+            for (key, value) in attributes {
+                let value = value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), str::to_owned);
+                writeln!(lines, "{key}={value}").unwrap();
+            }
+        }
+        lines
+    }
+}
+
+pub async fn subscribers(
+    query: web::Query<QueryParams>,
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let mut rows = String::new();
+    for subscriber in search_subscribers(&pool, &query.email)
+        .await
+        .map_err(opaque_500_err)?
+    {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{name}</td>
+                <td>{status}</td>
+                <td colspan="2">
+                    <form action="/admin/subscribers/{id}" method="post">
+                        <input type="text" name="tags" value="{tags}" placeholder="vip, beta">
+                        <textarea name="attributes" rows="3" cols="30" placeholder="region=emea">{attributes}</textarea>
+                        <button type="submit">Save</button>
+                    </form>
+                </td>
+                <td><a href="/admin/subscribers/data?email={email_param}">Download data</a></td>
+            </tr>"#,
+            id = subscriber.id,
+            email = html_escape(&subscriber.email),
+            email_param = urlencoding::encode(&subscriber.email),
+            name = html_escape(&subscriber.name),
+            status = html_escape(&subscriber.status),
+            tags = html_escape(&subscriber.tags.join(", ")),
+            attributes = html_escape(&subscriber.attribute_lines()),
+        )
+        .unwrap();
+    }
+    let mut list_options = String::new();
+    for list in get_lists(&pool).await.map_err(opaque_500_err)? {
+        writeln!(
+            list_options,
+            r#"<option value="{}">{}</option>"#,
+            list.list_id,
+            html_escape(&list.name)
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Subscribers</title>
+    </head>
+    <body>
+        {html_msg}
+        <form action="/admin/subscribers" method="get">
+            <input type="text" name="email" value="{search}" placeholder="Search by email">
+            <button type="submit">Search</button>
+        </form>
+        <table>
+            <tr>
+                <th>Email</th>
+                <th>Name</th>
+                <th>Status</th>
+                <th>Tags</th>
+                <th>Attributes</th>
+                <th></th>
+            </tr>
+            {rows}
+        </table>
+        <p><a href="/admin/subscribers/import">Import subscribers from a CSV file</a></p>
+        <h2>Data requests</h2>
+        <form action="/admin/subscribers/data" method="get">
+            <input type="email" name="email" placeholder="Email address" required>
+            <button type="submit">Download everything stored</button>
+        </form>
+        <form action="/admin/subscribers/erase" method="post">
+            <input type="email" name="email" placeholder="Email address" required>
+            <button type="submit">Erase everything stored</button>
+        </form>
+        <h2>Export</h2>
+        <form action="/admin/subscribers/export" method="get">
+            <label>List:
+                <select name="list_id">
+                    <option value="">All subscribers</option>
+                    {list_options}
+                </select>
+            </label>
+            <label>Status:
+                <select name="status">
+                    <option value="">Any</option>
+                    <option value="confirmed">Confirmed</option>
+                    <option value="pending_confirmation">Pending confirmation</option>
+                    <option value="unsubscribed">Unsubscribed</option>
+                </select>
+            </label>
+            <br>
+            <label>Subscribed from: <input type="date" name="from"></label>
+            <label>to: <input type="date" name="to"></label>
+            <br>
+            <label>Format:
+                <select name="format">
+                    <option value="csv">CSV</option>
+                    <option value="ndjson">JSON (one object per line)</option>
+                </select>
+            </label>
+            <button type="submit">Download</button>
+        </form>
+        <p>With a list, status and dates are those of the list membership.</p>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#,
+            search = html_escape(&query.email),
+        )))
+}
+
+/// Answers a data-subject access request for any address, including ones
+/// only found in the delivery logs.
+#[tracing::instrument(name = "Download subscriber data", skip_all)]
+pub async fn subscriber_data(
+    query: web::Query<DataQueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let data = get_subscriber_data(&pool, query.email.trim())
+        .await
+        .map_err(opaque_500_err)?;
+    Ok(data_download(&data))
+}
+
+#[tracing::instrument(name = "Search subscribers", skip(pool))]
+async fn search_subscribers(pool: &PgPool, email: &str) -> Result<Vec<Subscriber>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, tags, attributes
+        FROM subscriptions
+        WHERE email ILIKE '%' || $1 || '%'
+        ORDER BY email
+        LIMIT $2
+        "#,
+        email,
+        PAGE_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscribers from db.")?;
+    Ok(subscribers)
+}
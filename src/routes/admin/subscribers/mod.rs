@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{subscriber_data, subscribers};
+pub use post::{erase_subscriber_data, update_subscriber};
@@ -0,0 +1,120 @@
+use crate::{
+    domain::parse_identifier,
+    routes::erase_subscriber,
+    utils::{opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde_json::{Map, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    /// Comma-separated.
+    tags: String,
+    /// One `key=value` per line.
+    attributes: String,
+}
+
+#[tracing::instrument(name = "Update subscriber tags and attributes", skip(form, pool))]
+pub async fn update_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (tags, attributes) = match parse_tags(&form.tags)
+        .and_then(|tags| Ok((tags, parse_attributes(&form.attributes)?)))
+    {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET tags = $2, attributes = $3
+        WHERE id = $1
+        "#,
+        *subscriber_id,
+        &tags,
+        Value::Object(attributes)
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(opaque_500_err)?;
+    FlashMessage::info("The subscriber has been updated.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EraseFormData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Erase subscriber data on request", skip_all)]
+pub async fn erase_subscriber_data(
+    form: web::Form<EraseFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email.trim();
+    if email.is_empty() {
+        FlashMessage::error("Enter the address to erase.").send();
+        return Ok(see_other("/admin/subscribers"));
+    }
+    erase_subscriber(&pool, email)
+        .await
+        .map_err(opaque_500_err)?;
+    FlashMessage::info("Everything stored about the address has been erased.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+fn parse_tags(tags: &str) -> Result<Vec<String>, String> {
+    let mut parsed = tags
+        .split(',')
+        .filter(|tag| !tag.trim().is_empty())
+        .map(parse_identifier)
+        .collect::<Result<Vec<_>, _>>()?;
+    parsed.sort();
+    parsed.dedup();
+    Ok(parsed)
+}
+
+fn parse_attributes(attributes: &str) -> Result<Map<String, Value>, String> {
+    let mut parsed = Map::new();
+    for line in attributes.lines().filter(|line| !line.trim().is_empty()) {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("`{line}` should look like key=value."))?;
+        parsed.insert(
+            parse_identifier(key)?,
+            Value::String(value.trim().to_owned()),
+        );
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_attributes, parse_tags};
+    use claim::assert_err;
+
+    #[test]
+    fn tags_are_normalised_and_deduplicated() {
+        assert_eq!(parse_tags("VIP, beta,, vip ").unwrap(), vec!["beta", "vip"]);
+    }
+
+    #[test]
+    fn attributes_are_parsed_one_per_line() {
+        let attributes = parse_attributes("region = emea\r\n\nplan=pro plus\n").unwrap();
+        assert_eq!(attributes["region"], "emea");
+        assert_eq!(attributes["plan"], "pro plus");
+    }
+
+    #[test]
+    fn attribute_lines_without_a_value_are_rejected() {
+        assert_err!(parse_attributes("region"));
+    }
+}
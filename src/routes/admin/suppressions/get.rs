@@ -0,0 +1,134 @@
+use crate::{
+    suppression::SuppressionReason,
+    utils::{html_escape, opaque_500_err},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    email: String,
+}
+
+struct Suppression {
+    email_hash: String,
+    /// `None` for erased addresses.
+    email: Option<String>,
+    reason: String,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn suppressions(
+    query: web::Query<QueryParams>,
+    flash_msg: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let mut rows = String::new();
+    for suppression in search_suppressions(&pool, query.email.trim())
+        .await
+        .map_err(opaque_500_err)?
+    {
+        let email = match &suppression.email {
+            Some(email) => html_escape(email),
+            None => format!("<i>erased</i> ({}…)", &suppression.email_hash[..12]),
+        };
+        // Erased addresses must never be emailed again.
+        let remove = if suppression.reason == SuppressionReason::Erased.as_str() {
+            String::new()
+        } else {
+            format!(
+                r#"<form action="/admin/suppressions/remove" method="post">
+                        <input hidden type="text" name="email_hash" value="{}">
+                        <button type="submit">Remove</button>
+                    </form>"#,
+                suppression.email_hash
+            )
+        };
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{reason}</td>
+                <td>{created_at}</td>
+                <td>{remove}</td>
+            </tr>"#,
+            reason = html_escape(&suppression.reason),
+            created_at = suppression.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Suppressed Addresses</title>
+    </head>
+    <body>
+        {html_msg}
+        <p>These addresses are never emailed, whatever the status of their subscription.</p>
+        <form action="/admin/suppressions" method="get">
+            <input type="text" name="email" value="{search}" placeholder="Search by email">
+            <button type="submit">Search</button>
+        </form>
+        <table>
+            <tr>
+                <th>Email</th>
+                <th>Reason</th>
+                <th>Since</th>
+                <th></th>
+            </tr>
+            {rows}
+        </table>
+        <form action="/admin/suppressions" method="post">
+            <input type="email" name="email" placeholder="Email address" required>
+            <select name="reason">
+                <option value="manual">Manual</option>
+                <option value="hard_bounce">Hard bounce</option>
+                <option value="spam_complaint">Spam complaint</option>
+            </select>
+            <button type="submit">Suppress</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>"#,
+            search = html_escape(&query.email),
+        )))
+}
+
+/// Erased addresses only turn up when searching for the exact address.
+#[tracing::instrument(name = "Search suppressions", skip(pool))]
+async fn search_suppressions(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Vec<Suppression>, anyhow::Error> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email_hash, email, reason, created_at
+        FROM suppressions
+        WHERE email ILIKE '%' || $1 || '%' OR email_hash = hash_email($1)
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+        email,
+        PAGE_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve suppressions from db.")?;
+    Ok(suppressions)
+}
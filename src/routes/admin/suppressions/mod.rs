@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::suppressions;
+pub use post::{add_suppression, remove_suppression};
@@ -0,0 +1,76 @@
+use crate::{
+    domain::SubscriberEmail,
+    suppression::{suppress, unsuppress, SuppressionReason},
+    utils::{opaque_500_err, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    email: String,
+    reason: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    email_hash: String,
+}
+
+#[tracing::instrument(name = "Add a suppression", skip_all, fields(reason = %form.reason))]
+pub async fn add_suppression(
+    form: web::Form<AddFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AddFormData { email, reason } = form.0;
+    let email = match SubscriberEmail::parse_email(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    // Erasure goes through the data request form, which also deletes the data.
+    let reason = match SuppressionReason::parse(&reason) {
+        Ok(reason) if reason != SuppressionReason::Erased => reason,
+        _ => {
+            FlashMessage::error(format!("{reason} is not a suppression reason.")).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(opaque_500_err)?;
+    suppress(&mut transaction, email.as_ref(), reason)
+        .await
+        .context("Failed to suppress the address.")
+        .map_err(opaque_500_err)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")
+        .map_err(opaque_500_err)?;
+    FlashMessage::info(format!("{} will no longer be emailed.", email.as_ref())).send();
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(name = "Unsuppress an address", skip_all)]
+pub async fn remove_suppression(
+    form: web::Form<RemoveFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let removed = unsuppress(pool.get_ref(), &form.email_hash)
+        .await
+        .context("Failed to remove the suppression.")
+        .map_err(opaque_500_err)?;
+    if removed {
+        FlashMessage::info("The address can be emailed again.").send();
+    } else {
+        FlashMessage::error("That address was not suppressed, or was erased.").send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
@@ -0,0 +1,418 @@
+use crate::{
+    auth::password::basic_auth,
+    config::WebhookSettings,
+    routes::error_chain_fmt,
+    suppression::{suppress, suppression_reason, SuppressionReason},
+};
+use actix_web::{
+    http::{header::HeaderMap, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    InvalidPayload(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            // Makes the provider deliver the event again later.
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let WebhookError::AuthError(_) = self {
+            response.insert_header(("WWW-Authenticate", r#"Basic realm="email-events""#));
+        }
+        response.body(self.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EventKind {
+    Delivered,
+    SoftBounce,
+    HardBounce,
+    SpamComplaint,
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Delivered => "delivered",
+            EventKind::SoftBounce => "soft_bounce",
+            EventKind::HardBounce => "hard_bounce",
+            EventKind::SpamComplaint => "spam_complaint",
+        }
+    }
+
+    /// Events after which the address must not be emailed again.
+    fn suppression(&self) -> Option<SuppressionReason> {
+        match self {
+            EventKind::HardBounce => Some(SuppressionReason::HardBounce),
+            EventKind::SpamComplaint => Some(SuppressionReason::SpamComplaint),
+            EventKind::Delivered | EventKind::SoftBounce => None,
+        }
+    }
+}
+
+/// The fields we use from Postmark's delivery, bounce and spam complaint
+/// webhook payloads.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    /// Set on bounces and complaints.
+    email: Option<String>,
+    /// Set on deliveries.
+    recipient: Option<String>,
+    /// Postmark stopped sending to the address.
+    #[serde(default)]
+    inactive: bool,
+    description: Option<String>,
+    details: Option<String>,
+    bounced_at: Option<DateTime<Utc>>,
+    delivered_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct EmailEvent {
+    kind: EventKind,
+    recipient: String,
+    newsletter_issue_id: Option<Uuid>,
+    /// Redelivered events are recognised by it, so it is required.
+    provider_message_id: String,
+    detail: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+impl PostmarkEvent {
+    /// `None` for the record types we don't track (opens, clicks, ...).
+    fn parse(self) -> Result<Option<EmailEvent>, String> {
+        let (kind, recipient, occurred_at) = match self.record_type.as_str() {
+            "Delivery" => (EventKind::Delivered, self.recipient, self.delivered_at),
+            "Bounce" => {
+                let kind = match self.bounce_type.as_deref() {
+                    Some("SpamComplaint") => EventKind::SpamComplaint,
+                    Some("HardBounce" | "BadEmailAddress") => EventKind::HardBounce,
+                    _ if self.inactive => EventKind::HardBounce,
+                    _ => EventKind::SoftBounce,
+                };
+                (kind, self.email, self.bounced_at)
+            }
+            "SpamComplaint" => (EventKind::SpamComplaint, self.email, self.bounced_at),
+            _ => return Ok(None),
+        };
+        let recipient = recipient
+            .filter(|r| !r.trim().is_empty())
+            .ok_or_else(|| format!("{} event without a recipient.", self.record_type))?;
+        let provider_message_id = self
+            .message_id
+            .filter(|id| !id.trim().is_empty())
+            .ok_or_else(|| format!("{} event without a MessageID.", self.record_type))?;
+        let newsletter_issue_id = self
+            .metadata
+            .get("newsletter_issue_id")
+            .map(|id| Uuid::parse_str(id))
+            .transpose()
+            .map_err(|e| format!("Invalid newsletter issue id: {e}"))?;
+        let detail = match (self.description, self.details) {
+            (Some(description), Some(details)) if !details.is_empty() => {
+                Some(format!("{description} {details}"))
+            }
+            (description, details) => description.or(details),
+        };
+        Ok(Some(EmailEvent {
+            kind,
+            recipient: recipient.trim().to_owned(),
+            newsletter_issue_id,
+            provider_message_id,
+            detail,
+            occurred_at: occurred_at.unwrap_or_else(Utc::now),
+        }))
+    }
+}
+
+/// Receives delivery, bounce and spam complaint events from Postmark.
+/// Hard bounces and complaints unsubscribe the address and suppress it.
+#[tracing::instrument(name = "Receive an email event", skip_all)]
+pub async fn email_events(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    webhooks: web::Data<Option<WebhookSettings>>,
+) -> Result<HttpResponse, WebhookError> {
+    authenticate(request.headers(), webhooks.get_ref().as_ref())?;
+    let payload: serde_json::Value =
+        serde_json::from_slice(&body).map_err(|e| WebhookError::InvalidPayload(e.to_string()))?;
+    let event: PostmarkEvent = serde_json::from_value(payload.clone())
+        .map_err(|e| WebhookError::InvalidPayload(e.to_string()))?;
+    match event.parse().map_err(WebhookError::InvalidPayload)? {
+        Some(event) => record_event(&pool, &event, &payload).await?,
+        None => tracing::info!("Ignoring an untracked email event"),
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn authenticate(
+    headers: &HeaderMap,
+    expected: Option<&WebhookSettings>,
+) -> Result<(), WebhookError> {
+    let expected = expected.ok_or_else(|| {
+        WebhookError::AuthError(anyhow::anyhow!("No webhook credentials are configured."))
+    })?;
+    let credentials = basic_auth(headers).map_err(WebhookError::AuthError)?;
+    // Comparing digests keeps the timing independent of how much of the
+    // secret a guess got right.
+    let username_matches =
+        Sha256::digest(&credentials.username) == Sha256::digest(&expected.username);
+    let password_matches = Sha256::digest(credentials.password.expose_secret())
+        == Sha256::digest(expected.password.expose_secret());
+    if username_matches & password_matches {
+        Ok(())
+    } else {
+        Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )))
+    }
+}
+
+#[tracing::instrument(
+    name = "Record an email event",
+    skip_all,
+    fields(kind = event.kind.as_str(), newsletter_issue_id = ?event.newsletter_issue_id)
+)]
+async fn record_event(
+    pool: &PgPool,
+    event: &EmailEvent,
+    payload: &serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    // Erased addresses must not reappear, not even in the event log.
+    if suppression_reason(&mut *transaction, &event.recipient)
+        .await
+        .context("Failed to check the suppression list.")?
+        .as_deref()
+        == Some(SuppressionReason::Erased.as_str())
+    {
+        tracing::info!("Ignoring an event about an erased address");
+        return Ok(());
+    }
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            event_id,
+            kind,
+            recipient,
+            newsletter_issue_id,
+            provider_message_id,
+            detail,
+            payload,
+            occurred_at
+        )
+        VALUES (
+            $1,
+            $2,
+            $3,
+            (SELECT newsletter_issue_id FROM newsletter_issues WHERE newsletter_issue_id = $4),
+            $5,
+            $6,
+            $7,
+            $8
+        )
+        ON CONFLICT (provider_message_id, kind) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        event.kind.as_str(),
+        event.recipient,
+        event.newsletter_issue_id,
+        event.provider_message_id,
+        event.detail,
+        payload,
+        event.occurred_at
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the email event.")?
+    .rows_affected();
+    if n_inserted == 0 {
+        tracing::info!("Ignoring an email event that was already recorded");
+        return Ok(());
+    }
+    if let Some(reason) = event.kind.suppression() {
+        suppress(&mut transaction, &event.recipient, reason)
+            .await
+            .context("Failed to suppress the address.")?;
+        sqlx::query!(
+            r#"
+            WITH unsubscribed AS (
+                UPDATE subscriptions
+                SET status = 'unsubscribed'
+                WHERE lower(email) = lower($1)
+                RETURNING id
+            )
+            UPDATE list_subscriptions
+            SET status = 'unsubscribed'
+            WHERE subscriber_id IN (SELECT id FROM unsubscribed)
+            "#,
+            event.recipient
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to unsubscribe the address.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{authenticate, EventKind, PostmarkEvent};
+    use crate::config::WebhookSettings;
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use base64::{engine::general_purpose, Engine};
+    use claim::{assert_err, assert_none, assert_ok};
+    use secrecy::Secret;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn parse(payload: serde_json::Value) -> Option<super::EmailEvent> {
+        serde_json::from_value::<PostmarkEvent>(payload)
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn hard_bounces_are_traced_back_to_their_issue() {
+        let issue_id = Uuid::new_v4();
+        let event = parse(json!({
+            "RecordType": "Bounce",
+            "ID": 4323372036854775807_i64,
+            "Type": "HardBounce",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Email": "ursula@example.com",
+            "BouncedAt": "2024-03-01T16:33:54.9070259Z",
+            "Description": "The server was unable to deliver your message.",
+            "Details": "smtp;550 5.1.1 user unknown",
+            "Inactive": true,
+            "Metadata": { "newsletter_issue_id": issue_id.to_string() },
+        }))
+        .unwrap();
+        assert_eq!(event.kind, EventKind::HardBounce);
+        assert_eq!(event.recipient, "ursula@example.com");
+        assert_eq!(event.newsletter_issue_id, Some(issue_id));
+    }
+
+    #[test]
+    fn transient_bounces_are_soft() {
+        let event = parse(json!({
+            "RecordType": "Bounce",
+            "Type": "Transient",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Email": "ursula@example.com",
+            "Inactive": false,
+        }))
+        .unwrap();
+        assert_eq!(event.kind, EventKind::SoftBounce);
+        assert_none!(event.newsletter_issue_id);
+    }
+
+    #[test]
+    fn deliveries_use_the_recipient_field() {
+        let event = parse(json!({
+            "RecordType": "Delivery",
+            "MessageID": "00000000-0000-0000-0000-000000000000",
+            "Recipient": "ursula@example.com",
+            "DeliveredAt": "2024-03-01T16:33:54Z",
+            "Details": "Test delivery webhook details",
+        }))
+        .unwrap();
+        assert_eq!(event.kind, EventKind::Delivered);
+        assert_eq!(event.recipient, "ursula@example.com");
+    }
+
+    #[test]
+    fn untracked_record_types_are_ignored() {
+        assert_none!(parse(json!({
+            "RecordType": "Open",
+            "Recipient": "ursula@example.com",
+        })));
+    }
+
+    #[test]
+    fn bounces_without_a_recipient_are_rejected() {
+        let event = serde_json::from_value::<PostmarkEvent>(json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+        }))
+        .unwrap();
+        assert_err!(event.parse());
+    }
+
+    #[test]
+    fn events_without_a_message_id_are_rejected() {
+        let event = serde_json::from_value::<PostmarkEvent>(json!({
+            "RecordType": "Delivery",
+            "Recipient": "ursula@example.com",
+        }))
+        .unwrap();
+        assert_err!(event.parse());
+    }
+
+    #[test]
+    fn only_the_configured_credentials_are_accepted() {
+        let settings = WebhookSettings {
+            username: "postmark".into(),
+            password: Secret::new("webhook-secret".into()),
+        };
+        let headers = |credentials: &str| {
+            let mut headers = HeaderMap::new();
+            let value = format!("Basic {}", general_purpose::STANDARD.encode(credentials));
+            headers.insert(AUTHORIZATION, HeaderValue::from_str(&value).unwrap());
+            headers
+        };
+        assert_ok!(authenticate(
+            &headers("postmark:webhook-secret"),
+            Some(&settings)
+        ));
+        assert_err!(authenticate(&headers("postmark:guess"), Some(&settings)));
+        assert_err!(authenticate(&headers("postmark:webhook-secret"), None));
+        assert_err!(authenticate(&HeaderMap::new(), Some(&settings)));
+    }
+}
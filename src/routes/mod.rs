@@ -1,13 +1,23 @@
 mod admin;
+mod email_events;
 mod health_check;
 mod home;
 mod login;
 pub mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_email_change;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
 
 pub use admin::*;
+pub use email_events::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_email_change::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_unsubscribe::*;
@@ -1,17 +1,27 @@
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    domain::{ListSlug, NewSubscriber, SubscriberEmail, SubscriberName},
     email_client::EmailClient,
     startup::ApplicationBaseUrl,
+    suppression::suppression_reason,
 };
-use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use actix_web::{http::header::ContentType, http::StatusCode, web, HttpResponse, ResponseError};
 use anyhow::Context;
+use chrono::DateTime;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
 
+/// How long a confirmation link stays valid.
+pub const SUBSCRIPTION_TOKEN_TTL: chrono::Duration = chrono::Duration::hours(24);
+/// Minimum time between two confirmation emails sent to the same address.
+const CONFIRMATION_RESEND_INTERVAL: chrono::Duration = chrono::Duration::minutes(10);
+/// The list subscribers join when the form doesn't name one.
+const DEFAULT_LIST: &str = "newsletter";
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    list: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -84,19 +94,56 @@ pub async fn subscribe(
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let mut form = form.into_inner();
+    let list = ListSlug::parse(form.list.take().unwrap_or_else(|| DEFAULT_LIST.into()))
+        .map_err(SubscribeError::ValidationError)?;
+    let new_subscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
 
     let mut transaction = conn_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let list_id = get_list_id(&mut transaction, &list)
+        .await
+        .context("Failed to look up the mailing list.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!("There is no {} list.", list.as_ref()))
+        })?;
+
+    let subscriber_id = match insert_subscriber(&mut transaction, &new_subscriber)
+        .await
+        .context("Failed to insert new subscriber in the database.")?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let existing =
+                get_existing_subscriber(&mut transaction, &new_subscriber.email, list_id)
+                    .await
+                    .context("Failed to retrieve the existing subscriber.")?;
+            // The response is the same whatever happens here, so that it
+            // doesn't reveal whether the address is on the list.
+            let list_status = existing.list_status.as_deref().unwrap_or_default();
+            match resubscribe_action(list_status, existing.last_token_at, Utc::now()) {
+                ResubscribeAction::Ignore => return Ok(HttpResponse::Ok().finish()),
+                ResubscribeAction::RateLimited => {
+                    tracing::info!("A confirmation email was sent recently, not sending another");
+                    return Ok(HttpResponse::Ok().finish());
+                }
+                ResubscribeAction::SendConfirmation => {}
+            }
+            reset_to_pending(&mut transaction, existing.id)
+                .await
+                .context("Failed to update the existing subscriber.")?;
+            existing.id
+        }
+    };
+    join_list(&mut transaction, list_id, subscriber_id)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to add the subscriber to the mailing list.")?;
 
     let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    store_token(&mut transaction, subscriber_id, &subscription_token, None)
         .await
         .context("Failed to store subscription token in the database.")?;
 
@@ -106,6 +153,7 @@ pub async fn subscribe(
         .context("Failed to commit SQL transaction.")?;
 
     send_confirmation_email(
+        &conn_pool,
         &email_client,
         &new_subscriber,
         &base_url.0,
@@ -117,6 +165,7 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Returns `None` if the address is already known.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -124,32 +173,166 @@ pub async fn subscribe(
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<uuid::Uuid, sqlx::Error> {
+) -> Result<Option<uuid::Uuid>, sqlx::Error> {
     let subscriber_id = uuid::Uuid::new_v4();
-    sqlx::query!(
+    let n_inserted = sqlx::query!(
         r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'confirmed')"#,
+        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        ON CONFLICT (email) DO NOTHING"#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
     .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    Ok((n_inserted == 1).then_some(subscriber_id))
+}
+
+struct ExistingSubscriber {
+    id: uuid::Uuid,
+    /// Status of their membership of the list they are subscribing to.
+    list_status: Option<String>,
+    last_token_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ResubscribeAction {
+    Ignore,
+    RateLimited,
+    SendConfirmation,
+}
+
+fn resubscribe_action(
+    list_status: &str,
+    last_token_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> ResubscribeAction {
+    if list_status == "confirmed" {
+        return ResubscribeAction::Ignore;
+    }
+    match last_token_at {
+        Some(last_token_at) if now - last_token_at < CONFIRMATION_RESEND_INTERVAL => {
+            ResubscribeAction::RateLimited
+        }
+        _ => ResubscribeAction::SendConfirmation,
+    }
+}
+
+#[tracing::instrument(name = "Get existing subscriber", skip_all)]
+async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+    list_id: uuid::Uuid,
+) -> Result<ExistingSubscriber, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT
+            id,
+            (
+                SELECT status
+                FROM list_subscriptions
+                WHERE list_id = $2 AND subscriber_id = subscriptions.id
+            ) AS list_status,
+            (
+                SELECT max(created_at)
+                FROM subscription_tokens
+                WHERE subscriber_id = subscriptions.id
+            ) AS last_token_at
+        FROM subscriptions
+        WHERE email = $1
+        FOR UPDATE
+        "#,
+        email.as_ref(),
+        list_id
+    )
+    .fetch_one(&mut **transaction)
+    .await
+}
+
+/// Lets unsubscribed addresses go through confirmation again. Confirmed
+/// addresses joining another list stay confirmed for their other lists.
+/// The stored name is kept, since whoever filled in the form hasn't proven
+/// they own the address.
+#[tracing::instrument(name = "Reset subscriber to pending", skip(transaction))]
+async fn reset_to_pending(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: uuid::Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = CASE
+            WHEN status = 'unsubscribed' THEN 'pending_confirmation'
+            ELSE status
+        END
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
     .await?;
+    Ok(())
+}
 
-    Ok(subscriber_id)
+#[tracing::instrument(name = "Get list id", skip(transaction))]
+async fn get_list_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    list: &ListSlug,
+) -> Result<Option<uuid::Uuid>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT list_id FROM lists WHERE slug = $1", list.as_ref())
+        .fetch_optional(&mut **transaction)
+        .await
 }
 
+/// The membership only becomes active once the subscriber confirms.
+#[tracing::instrument(name = "Join a mailing list", skip(transaction))]
+async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: uuid::Uuid,
+    subscriber_id: uuid::Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT (list_id, subscriber_id)
+        DO UPDATE SET status = 'pending_confirmation', subscribed_at = now()
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Does nothing for suppressed addresses, without telling the caller, so
+/// that the subscribe form doesn't reveal the suppression list either.
 #[tracing::instrument(
     name = "Sending confirmation email",
-    skip(email_client, new_subscriber, base_url)
+    skip(conn_pool, email_client, new_subscriber, base_url)
 )]
 pub async fn send_confirmation_email(
+    conn_pool: &PgPool,
     email_client: &EmailClient,
     new_subscriber: &NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
+    if let Some(reason) = suppression_reason(conn_pool, new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!(
+            reason,
+            "Not sending a confirmation email to a suppressed address"
+        );
+        return Ok(());
+    }
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -174,7 +357,7 @@ pub async fn send_confirmation_email(
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut thread = thread_rng();
     std::iter::repeat_with(|| thread.sample(Alphanumeric))
         .map(char::from)
@@ -187,12 +370,17 @@ pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: uuid::Uuid,
     subscription_token: &str,
+    new_email: Option<&str>,
 ) -> Result<(), TokenError> {
+    let created_at = Utc::now();
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at, expires_at, new_email)
+        VALUES ($1, $2, $3, $4, $5)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        created_at,
+        created_at + SUBSCRIPTION_TOKEN_TTL,
+        new_email
     )
     .execute(&mut **transaction)
     .await
@@ -212,3 +400,55 @@ pub fn error_chain_fmt(
     }
     Ok(())
 }
+
+/// The page for signed links that don't verify, shared by the pages
+/// subscribers reach from our emails.
+pub fn invalid_link(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Invalid link</title>
+    </head>
+    <body>
+        <p>{message}</p>
+    </body>
+    </html>"#
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resubscribe_action, ResubscribeAction, CONFIRMATION_RESEND_INTERVAL};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn confirmed_subscribers_are_ignored() {
+        let action = resubscribe_action("confirmed", None, Utc::now());
+        assert_eq!(action, ResubscribeAction::Ignore);
+    }
+
+    #[test]
+    fn pending_subscribers_get_a_new_confirmation_email() {
+        let now = Utc::now();
+        let last_token_at = now - CONFIRMATION_RESEND_INTERVAL - Duration::seconds(1);
+        let action = resubscribe_action("pending_confirmation", Some(last_token_at), now);
+        assert_eq!(action, ResubscribeAction::SendConfirmation);
+    }
+
+    #[test]
+    fn confirmation_emails_are_rate_limited() {
+        let now = Utc::now();
+        let action = resubscribe_action("pending_confirmation", Some(now), now);
+        assert_eq!(action, ResubscribeAction::RateLimited);
+    }
+
+    #[test]
+    fn unsubscribed_addresses_can_opt_back_in() {
+        let action = resubscribe_action("unsubscribed", None, Utc::now());
+        assert_eq!(action, ResubscribeAction::SendConfirmation);
+    }
+}
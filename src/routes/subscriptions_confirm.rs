@@ -1,60 +1,194 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use crate::utils::opaque_500_err;
+use actix_web::{http::header::ContentType, http::StatusCode, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 
 #[derive(serde::Deserialize)]
 pub struct QueryParameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(params))]
+struct StoredToken {
+    subscriber_id: uuid::Uuid,
+    expires_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+    new_email: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenState {
+    Usable,
+    Expired,
+    Consumed,
+}
+
+impl StoredToken {
+    fn state(&self, now: DateTime<Utc>) -> TokenState {
+        if self.consumed_at.is_some() {
+            TokenState::Consumed
+        } else if self.expires_at <= now {
+            TokenState::Expired
+        } else {
+            TokenState::Usable
+        }
+    }
+}
+
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(params, conn_pool))]
 pub async fn confirm(
     params: web::Query<QueryParameters>,
     conn_pool: web::Data<PgPool>,
-) -> HttpResponse {
-    let subscriber_id = match get_subscriber_id(&conn_pool, &params.subscription_token).await {
-        Ok(subscriber_id) => subscriber_id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = conn_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(opaque_500_err)?;
+    let token = get_token(&mut transaction, &params.subscription_token)
+        .await
+        .map_err(opaque_500_err)?;
+    let token = match token {
+        Some(token) => token,
+        None => {
+            return Ok(confirmation_page(
+                StatusCode::UNAUTHORIZED,
+                "Invalid confirmation link",
+                "This confirmation link is not valid. Please check that you copied it in full.",
+            ))
+        }
     };
-    match subscriber_id {
-        Some(id) => {
-            if confirm_subscriber(&conn_pool, id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
+    match token.state(Utc::now()) {
+        TokenState::Consumed => Ok(match token.new_email {
+            Some(_) => confirmation_page(
+                StatusCode::OK,
+                "Already changed",
+                "This link has already been used. Your address has been changed.",
+            ),
+            None => confirmation_page(
+                StatusCode::OK,
+                "Already confirmed",
+                "This link has already been used. Your subscription is confirmed.",
+            ),
+        }),
+        TokenState::Expired => Ok(confirmation_page(
+            StatusCode::GONE,
+            "Confirmation link expired",
+            "This confirmation link has expired. Please subscribe again to receive a new one.",
+        )),
+        TokenState::Usable => {
+            consume_token(&mut transaction, &params.subscription_token)
+                .await
+                .map_err(opaque_500_err)?;
+            let page = match &token.new_email {
+                Some(new_email) => {
+                    let changed = change_email(&mut transaction, token.subscriber_id, new_email)
+                        .await
+                        .map_err(opaque_500_err)?;
+                    if !changed {
+                        // The token is left unused, so that the link works
+                        // again once the address is free.
+                        transaction
+                            .rollback()
+                            .await
+                            .context("Failed to roll back SQL transaction.")
+                            .map_err(opaque_500_err)?;
+                        return Ok(confirmation_page(
+                            StatusCode::CONFLICT,
+                            "Address already in use",
+                            "This address is already subscribed, so we kept your previous one.",
+                        ));
+                    }
+                    confirmation_page(
+                        StatusCode::OK,
+                        "Address changed",
+                        "Your new address is confirmed. Future issues will be sent there.",
+                    )
+                }
+                None => {
+                    confirm_subscriber(&mut transaction, token.subscriber_id)
+                        .await
+                        .map_err(opaque_500_err)?;
+                    confirmation_page(
+                        StatusCode::OK,
+                        "Subscription confirmed",
+                        "Thanks for confirming your subscription!",
+                    )
+                }
+            };
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction.")
+                .map_err(opaque_500_err)?;
+            Ok(page)
         }
-        None => HttpResponse::Unauthorized().finish(),
     }
 }
 
-#[tracing::instrument(
-    name = "Get subscriber ID from token",
-    skip(conn_pool, subscription_token)
-)]
-pub async fn get_subscriber_id(
-    conn_pool: &PgPool,
+fn confirmation_page(status: StatusCode, title: &str, message: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>{title}</title>
+    </head>
+    <body>
+        <h1>{title}</h1>
+        <p>{message}</p>
+    </body>
+    </html>"#
+        ))
+}
+
+/// Locks the token row, so that two concurrent clicks cannot both consume it.
+#[tracing::instrument(name = "Get subscription token", skip_all)]
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<uuid::Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
+) -> Result<Option<StoredToken>, anyhow::Error> {
+    let token = sqlx::query_as!(
+        StoredToken,
+        r#"
+        SELECT subscriber_id, expires_at, consumed_at, new_email FROM subscription_tokens
+        WHERE subscription_token = $1
+        FOR UPDATE
+        "#,
+        subscription_token
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to retrieve the subscription token.")?;
+    Ok(token)
+}
+
+#[tracing::instrument(name = "Consume subscription token", skip_all)]
+async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
         r#"
-        SELECT subscriber_id FROM subscription_tokens
+        UPDATE subscription_tokens
+        SET consumed_at = now()
         WHERE subscription_token = $1
         "#,
         subscription_token
     )
-    .fetch_optional(conn_pool)
+    .execute(&mut **transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Error selecting subscriber ID: {:?}", e);
-        e
-    })?;
-    Ok(result.map(|res| res.subscriber_id))
+    .context("Failed to mark the subscription token as used.")?;
+    Ok(())
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(conn_pool, subscriber_id))]
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(transaction))]
 pub async fn confirm_subscriber(
-    conn_pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: uuid::Uuid,
-) -> Result<(), sqlx::Error> {
+) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         UPDATE subscriptions
@@ -63,11 +197,109 @@ pub async fn confirm_subscriber(
         "#,
         subscriber_id
     )
-    .execute(conn_pool)
+    .execute(&mut **transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Error updating status: {:?}", e);
-        e
-    })?;
+    .context("Failed to mark the subscriber as confirmed.")?;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions
+        SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to confirm the subscriber's list memberships.")?;
     Ok(())
 }
+
+/// Returns `false` if another subscriber already uses `new_email`, including
+/// when a concurrent change took it first. The transaction can't be used any
+/// further in that case.
+#[tracing::instrument(name = "Change subscriber email", skip(transaction))]
+async fn change_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: uuid::Uuid,
+    new_email: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET email = $2
+        WHERE id = $1
+            AND NOT EXISTS (SELECT 1 FROM subscriptions WHERE email = $2 AND id <> $1)
+        "#,
+        subscriber_id,
+        new_email
+    )
+    .execute(&mut **transaction)
+    .await;
+    match result {
+        Ok(result) => Ok(result.rows_affected() == 1),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(false),
+        Err(e) => Err(e).context("Failed to change the subscriber's email."),
+    }
+}
+
+/// Deletes tokens that expired (or were used) more than `retention` ago.
+/// They are kept around for a while so that late clicks still get a
+/// meaningful page instead of "invalid link".
+#[tracing::instrument(name = "Sweep stale subscription tokens", skip(conn_pool))]
+pub async fn sweep_stale_tokens(
+    conn_pool: &PgPool,
+    retention: chrono::Duration,
+) -> Result<u64, anyhow::Error> {
+    let n_deleted = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE COALESCE(consumed_at, expires_at) < $1
+        "#,
+        Utc::now() - retention
+    )
+    .execute(conn_pool)
+    .await
+    .context("Failed to delete stale subscription tokens.")?
+    .rows_affected();
+    Ok(n_deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StoredToken, TokenState};
+    use chrono::{Duration, Utc};
+
+    fn token(expires_in: Duration, consumed: bool) -> StoredToken {
+        let now = Utc::now();
+        StoredToken {
+            subscriber_id: uuid::Uuid::new_v4(),
+            expires_at: now + expires_in,
+            consumed_at: consumed.then_some(now),
+            new_email: None,
+        }
+    }
+
+    #[test]
+    fn fresh_token_is_usable() {
+        assert_eq!(
+            token(Duration::hours(1), false).state(Utc::now()),
+            TokenState::Usable
+        );
+    }
+
+    #[test]
+    fn token_past_its_expiry_is_expired() {
+        assert_eq!(
+            token(Duration::hours(-1), false).state(Utc::now()),
+            TokenState::Expired
+        );
+    }
+
+    #[test]
+    fn used_token_is_consumed_even_after_expiry() {
+        assert_eq!(
+            token(Duration::hours(-1), true).state(Utc::now()),
+            TokenState::Consumed
+        );
+    }
+}
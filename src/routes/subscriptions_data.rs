@@ -0,0 +1,388 @@
+use crate::{
+    domain::{ScopedToken, SubscriberEmail, TokenPurpose, UnsubscribeToken},
+    email_client::EmailClient,
+    routes::invalid_link,
+    startup::{ApplicationBaseUrl, HmacSecretKey},
+    suppression::{email_hash, suppress, suppression_reason, SuppressionReason},
+    utils::{opaque_500_err, see_other},
+};
+use actix_web::{
+    http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How long the links sent by `request_data_links` stay valid.
+const DATA_LINK_TTL: chrono::Duration = chrono::Duration::hours(24);
+const INVALID_LINK: &str =
+    "This link is not valid or has expired, or your data has already been erased.";
+
+#[derive(serde::Deserialize)]
+pub struct DataParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+/// Links to the subscriber's own data, with a token of their own: issues
+/// get forwarded, and their links end up in logs and link scanners.
+fn data_links(base_url: &str, subscriber_id: Uuid, secret: &Secret<String>) -> (String, String) {
+    let token = ScopedToken::issue(
+        TokenPurpose::DataAccess,
+        subscriber_id,
+        Utc::now() + DATA_LINK_TTL,
+        secret,
+    );
+    let query = format!("subscriber_id={subscriber_id}&token={}", token.as_ref());
+    (
+        format!("{base_url}/subscriptions/data?{query}"),
+        format!("{base_url}/subscriptions/erase?{query}"),
+    )
+}
+
+/// Emails the data links to the subscriber's current address, when asked
+/// from their preferences page.
+#[tracing::instrument(
+    name = "Send data links",
+    skip_all,
+    fields(subscriber_id = %params.subscriber_id)
+)]
+pub async fn request_data_links(
+    params: web::Query<DataParameters>,
+    conn_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    secret: web::Data<HmacSecretKey>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if UnsubscribeToken::verify(params.subscriber_id, &params.token, &secret.0).is_err() {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    let Some(email) = subscriber_email(&conn_pool, params.subscriber_id)
+        .await
+        .map_err(opaque_500_err)?
+    else {
+        return Ok(invalid_link(INVALID_LINK));
+    };
+    let (data_url, erase_url) = data_links(&base_url.0, params.subscriber_id, &secret.0);
+    send_data_links(&conn_pool, &email_client, &email, &data_url, &erase_url)
+        .await
+        .context("Failed to send the data links.")
+        .map_err(opaque_500_err)?;
+    FlashMessage::info(
+        "We have emailed you links to download or erase your data. They are valid for 24 hours.",
+    )
+    .send();
+    Ok(see_other(&format!(
+        "/subscriptions/preferences?subscriber_id={}&token={}",
+        params.subscriber_id, params.token
+    )))
+}
+
+#[tracing::instrument(name = "Download own subscriber data", skip_all)]
+pub async fn download_data(
+    params: web::Query<DataParameters>,
+    conn_pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecretKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = verified_email(&params, &conn_pool, &secret)
+        .await
+        .map_err(opaque_500_err)?
+    else {
+        return Ok(invalid_link(INVALID_LINK));
+    };
+    let data = get_subscriber_data(&conn_pool, &email)
+        .await
+        .map_err(opaque_500_err)?;
+    Ok(data_download(&data))
+}
+
+/// Asks for a confirmation first, since link scanners and prefetchers follow
+/// every link they find.
+#[tracing::instrument(name = "Show erasure page", skip_all)]
+pub async fn erase_form(
+    params: web::Query<DataParameters>,
+    secret: web::Data<HmacSecretKey>,
+) -> HttpResponse {
+    if ScopedToken::verify(
+        TokenPurpose::DataAccess,
+        params.subscriber_id,
+        &params.token,
+        &secret.0,
+    )
+    .is_err()
+    {
+        return invalid_link(INVALID_LINK);
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Erase your data</title>
+    </head>
+    <body>
+        <p>This deletes everything we store about you and unsubscribes you from every list.
+        We only keep a one-way hash of your address, so that it is never added back. This cannot be undone.</p>
+        <form action="/subscriptions/erase?subscriber_id={}&token={}" method="post">
+            <button type="submit">Erase my data</button>
+        </form>
+    </body>
+    </html>"#,
+            params.subscriber_id, params.token
+        ))
+}
+
+#[tracing::instrument(
+    name = "Erase own subscriber data",
+    skip_all,
+    fields(subscriber_id = %params.subscriber_id)
+)]
+pub async fn erase(
+    params: web::Query<DataParameters>,
+    conn_pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecretKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = verified_email(&params, &conn_pool, &secret)
+        .await
+        .map_err(opaque_500_err)?
+    else {
+        return Ok(invalid_link(INVALID_LINK));
+    };
+    erase_subscriber(&conn_pool, &email)
+        .await
+        .map_err(opaque_500_err)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Data erased</title>
+    </head>
+    <body>
+        <p>Your data has been erased. You won't hear from us again.</p>
+    </body>
+    </html>"#,
+    ))
+}
+
+/// `None` if the link is forged or expired, or the subscriber no longer
+/// exists.
+async fn verified_email(
+    params: &DataParameters,
+    conn_pool: &PgPool,
+    secret: &HmacSecretKey,
+) -> Result<Option<String>, anyhow::Error> {
+    if ScopedToken::verify(
+        TokenPurpose::DataAccess,
+        params.subscriber_id,
+        &params.token,
+        &secret.0,
+    )
+    .is_err()
+    {
+        return Ok(None);
+    }
+    subscriber_email(conn_pool, params.subscriber_id).await
+}
+
+pub async fn subscriber_email(
+    conn_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let email = sqlx::query_scalar!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(conn_pool)
+    .await
+    .context("Failed to retrieve the subscriber's email.")?;
+    Ok(email)
+}
+
+#[tracing::instrument(name = "Sending data links", skip_all)]
+async fn send_data_links(
+    conn_pool: &PgPool,
+    email_client: &EmailClient,
+    email: &str,
+    data_url: &str,
+    erase_url: &str,
+) -> Result<(), anyhow::Error> {
+    if suppression_reason(conn_pool, email)
+        .await
+        .context("Failed to check the suppression list.")?
+        .is_some()
+    {
+        tracing::info!("Not sending data links to a suppressed address");
+        return Ok(());
+    }
+    let email = SubscriberEmail::parse_email(email.to_owned()).map_err(anyhow::Error::msg)?;
+    let plain_text_content = format!(
+        "Download everything we store about you: {data_url}\n\
+        Erase it: {erase_url}\n\n\
+        These links are valid for 24 hours. If you didn't ask for them, you can ignore this email."
+    );
+    let html_content = format!(
+        "<p><a href=\"{data_url}\">Download everything we store about you</a> \
+        or <a href=\"{erase_url}\">erase it</a>.</p>\
+        <p>These links are valid for 24 hours. If you didn't ask for them, you can ignore this email.</p>"
+    );
+    email_client
+        .send_email(&email, "Your data", &html_content, &plain_text_content)
+        .await?;
+    Ok(())
+}
+
+/// Serves the output of `get_subscriber_data` as a JSON file.
+pub fn data_download(data: &serde_json::Value) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscriber-data.json".into())],
+        })
+        .body(data.to_string())
+}
+
+/// Everything stored about an address, for data-subject access requests.
+/// Any new table holding subscriber data must be added here and to
+/// `erase_subscriber`.
+#[tracing::instrument(name = "Collect subscriber data", skip_all)]
+pub async fn get_subscriber_data(
+    conn_pool: &PgPool,
+    email: &str,
+) -> Result<serde_json::Value, anyhow::Error> {
+    let data = sqlx::query_scalar!(
+        r#"
+        SELECT jsonb_build_object(
+            'email', $1::text,
+            'subscription', (
+                SELECT to_jsonb(s) FROM subscriptions s WHERE lower(s.email) = lower($1)
+            ),
+            'list_memberships', COALESCE((
+                SELECT jsonb_agg(to_jsonb(ls) || jsonb_build_object('list', l.slug))
+                FROM list_subscriptions ls
+                JOIN lists l ON l.list_id = ls.list_id
+                JOIN subscriptions s ON s.id = ls.subscriber_id
+                WHERE lower(s.email) = lower($1)
+            ), '[]'),
+            'subscription_tokens', COALESCE((
+                SELECT jsonb_agg(to_jsonb(t) ORDER BY t.created_at)
+                FROM subscription_tokens t
+                LEFT JOIN subscriptions s ON s.id = t.subscriber_id
+                WHERE lower(s.email) = lower($1) OR lower(t.new_email) = lower($1)
+            ), '[]'),
+            'pending_deliveries', COALESCE((
+                SELECT jsonb_agg(to_jsonb(q))
+                FROM issue_delivery_queue q
+                WHERE lower(q.subscriber_email) = lower($1)
+            ), '[]'),
+            'failed_deliveries', COALESCE((
+                SELECT jsonb_agg(to_jsonb(d) ORDER BY d.failed_at)
+                FROM issue_delivery_dead_letters d
+                WHERE lower(d.subscriber_email) = lower($1)
+            ), '[]'),
+            'delivery_log', COALESCE((
+                SELECT jsonb_agg(to_jsonb(dl) ORDER BY dl.logged_at)
+                FROM issue_delivery_log dl
+                WHERE lower(dl.subscriber_email) = lower($1)
+            ), '[]'),
+            'email_events', COALESCE((
+                SELECT jsonb_agg(to_jsonb(e) ORDER BY e.occurred_at)
+                FROM email_events e
+                WHERE lower(e.recipient) = lower($1)
+            ), '[]'),
+            'suppression', (
+                SELECT to_jsonb(x) FROM suppressions x WHERE x.email_hash = $2
+            )
+        ) AS "data!"
+        "#,
+        email,
+        email_hash(email)
+    )
+    .fetch_one(conn_pool)
+    .await
+    .context("Failed to collect the subscriber's data.")?;
+    Ok(data)
+}
+
+/// Deletes everything stored about an address (tokens and list memberships
+/// go along with the subscriber), leaving only a hashed suppression entry so
+/// that it is never imported again.
+#[tracing::instrument(name = "Erase subscriber data", skip_all)]
+pub async fn erase_subscriber(conn_pool: &PgPool, email: &str) -> Result<(), anyhow::Error> {
+    let mut transaction = conn_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE lower(new_email) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete pending email changes.")?;
+    sqlx::query!(
+        "DELETE FROM subscriptions WHERE lower(email) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscriber.")?;
+    // Undelivered tasks are no longer part of their issue's audience.
+    sqlx::query!(
+        r#"
+        WITH erased AS (
+            DELETE FROM issue_delivery_queue
+            WHERE lower(subscriber_email) = lower($1)
+            RETURNING newsletter_issue_id
+        )
+        UPDATE newsletter_issues i
+        SET n_total = n_total - e.n_erased
+        FROM (
+            SELECT newsletter_issue_id, count(*)::int AS n_erased
+            FROM erased
+            GROUP BY newsletter_issue_id
+        ) e
+        WHERE i.newsletter_issue_id = e.newsletter_issue_id
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete pending deliveries.")?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_dead_letters WHERE lower(subscriber_email) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete failed deliveries.")?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_log WHERE lower(subscriber_email) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the delivery log.")?;
+    sqlx::query!(
+        "DELETE FROM email_events WHERE lower(recipient) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the email events.")?;
+    suppress(&mut transaction, email, SuppressionReason::Erased)
+        .await
+        .context("Failed to suppress the address.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
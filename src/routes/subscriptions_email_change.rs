@@ -0,0 +1,269 @@
+use crate::{
+    domain::{ScopedToken, SubscriberEmail, TokenPurpose, UnsubscribeToken},
+    email_client::EmailClient,
+    routes::{generate_subscription_token, invalid_link, store_token, subscriber_email},
+    startup::{ApplicationBaseUrl, HmacSecretKey},
+    suppression::suppression_reason,
+    utils::{html_escape, opaque_500_err, see_other},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// How long the links sent by `request_email_change` stay valid.
+const EMAIL_CHANGE_LINK_TTL: chrono::Duration = chrono::Duration::hours(1);
+const INVALID_LINK: &str =
+    "This link is not valid or has expired. Please ask for a new one from your preferences page.";
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeFormData {
+    email: String,
+}
+
+/// Emails a link to change the address to the current address, when asked
+/// from the preferences page. Issues get forwarded, so their links alone
+/// must not be enough to move a subscription elsewhere.
+#[tracing::instrument(
+    name = "Send email change link",
+    skip_all,
+    fields(subscriber_id = %params.subscriber_id)
+)]
+pub async fn request_email_change(
+    params: web::Query<EmailChangeParameters>,
+    conn_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    secret: web::Data<HmacSecretKey>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if UnsubscribeToken::verify(params.subscriber_id, &params.token, &secret.0).is_err() {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    let Some(email) = subscriber_email(&conn_pool, params.subscriber_id)
+        .await
+        .map_err(opaque_500_err)?
+    else {
+        return Ok(invalid_link(INVALID_LINK));
+    };
+    let token = ScopedToken::issue(
+        TokenPurpose::EmailChange,
+        params.subscriber_id,
+        Utc::now() + EMAIL_CHANGE_LINK_TTL,
+        &secret.0,
+    );
+    let link = format!(
+        "{}/subscriptions/email?subscriber_id={}&token={}",
+        base_url.0,
+        params.subscriber_id,
+        token.as_ref()
+    );
+    send_email_change_link(&conn_pool, &email_client, &email, &link)
+        .await
+        .context("Failed to send the email change link.")
+        .map_err(opaque_500_err)?;
+    FlashMessage::info(
+        "We have emailed you a link to change your address. It is valid for an hour.",
+    )
+    .send();
+    Ok(see_other(&format!(
+        "/subscriptions/preferences?subscriber_id={}&token={}",
+        params.subscriber_id, params.token
+    )))
+}
+
+#[tracing::instrument(name = "Show email change form", skip_all)]
+pub async fn email_change_form(
+    params: web::Query<EmailChangeParameters>,
+    flash_msg: IncomingFlashMessages,
+    secret: web::Data<HmacSecretKey>,
+) -> HttpResponse {
+    if verify(&params, &secret).is_err() {
+        return invalid_link(INVALID_LINK);
+    }
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Change your address</title>
+    </head>
+    <body>
+        {html_msg}
+        <form action="/subscriptions/email?subscriber_id={}&token={}" method="post">
+            <label>New email address (we will ask you to confirm it):<br>
+                <input type="email" name="email">
+            </label>
+            <br>
+            <button type="submit">Change my address</button>
+        </form>
+    </body>
+    </html>"#,
+            params.subscriber_id, params.token
+        ))
+}
+
+/// The new address only replaces the current one once it has been
+/// confirmed.
+#[tracing::instrument(
+    name = "Change subscriber email",
+    skip_all,
+    fields(subscriber_id = %params.subscriber_id)
+)]
+pub async fn update_email(
+    params: web::Query<EmailChangeParameters>,
+    form: web::Form<EmailChangeFormData>,
+    conn_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    secret: web::Data<HmacSecretKey>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if verify(&params, &secret).is_err() {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    let back = format!(
+        "/subscriptions/email?subscriber_id={}&token={}",
+        params.subscriber_id, params.token
+    );
+    let email = match SubscriberEmail::parse_email(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+    let Some(current_email) = subscriber_email(&conn_pool, params.subscriber_id)
+        .await
+        .map_err(opaque_500_err)?
+    else {
+        return Ok(invalid_link(INVALID_LINK));
+    };
+    if email.as_ref() == current_email {
+        FlashMessage::error("This already is your address.").send();
+        return Ok(see_other(&back));
+    }
+
+    let mut transaction = conn_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(opaque_500_err)?;
+    let token = generate_subscription_token();
+    store_token(
+        &mut transaction,
+        params.subscriber_id,
+        &token,
+        Some(email.as_ref()),
+    )
+    .await
+    .context("Failed to store the email change token.")
+    .map_err(opaque_500_err)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")
+        .map_err(opaque_500_err)?;
+    send_email_change_confirmation(&conn_pool, &email_client, &email, &base_url.0, &token)
+        .await
+        .context("Failed to send the email change confirmation.")
+        .map_err(opaque_500_err)?;
+    FlashMessage::info("Please check your new inbox to confirm the change of address.").send();
+    Ok(see_other(&back))
+}
+
+fn verify(params: &EmailChangeParameters, secret: &HmacSecretKey) -> Result<(), anyhow::Error> {
+    ScopedToken::verify(
+        TokenPurpose::EmailChange,
+        params.subscriber_id,
+        &params.token,
+        &secret.0,
+    )
+}
+
+#[tracing::instrument(name = "Sending email change link", skip_all)]
+async fn send_email_change_link(
+    conn_pool: &PgPool,
+    email_client: &EmailClient,
+    email: &str,
+    link: &str,
+) -> Result<(), anyhow::Error> {
+    if suppression_reason(conn_pool, email)
+        .await
+        .context("Failed to check the suppression list.")?
+        .is_some()
+    {
+        tracing::info!("Not sending an email change link to a suppressed address");
+        return Ok(());
+    }
+    let email = SubscriberEmail::parse_email(email.to_owned()).map_err(anyhow::Error::msg)?;
+    let plain_text_content = format!(
+        "Visit {link} to receive our newsletter at another address. \
+        The link is valid for an hour. If you didn't ask for it, you can ignore this email."
+    );
+    let html_content = format!(
+        "Click <a href=\"{link}\">here</a> to receive our newsletter at another address. \
+        The link is valid for an hour. If you didn't ask for it, you can ignore this email."
+    );
+    email_client
+        .send_email(
+            &email,
+            "Change your address",
+            &html_content,
+            &plain_text_content,
+        )
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Sending email change confirmation", skip_all)]
+async fn send_email_change_confirmation(
+    conn_pool: &PgPool,
+    email_client: &EmailClient,
+    new_email: &SubscriberEmail,
+    base_url: &str,
+    subscription_token: &str,
+) -> Result<(), anyhow::Error> {
+    if suppression_reason(conn_pool, new_email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+        .is_some()
+    {
+        tracing::info!("Not sending an email change confirmation to a suppressed address");
+        return Ok(());
+    }
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url, subscription_token
+    );
+    let plain_text_content = format!(
+        "Visit {} to receive our newsletter at this address from now on.",
+        confirmation_link
+    );
+    let html_content = format!(
+        "Click <a href=\"{}\">here</a> to receive our newsletter at this address from now on.",
+        confirmation_link
+    );
+    email_client
+        .send_email(
+            new_email,
+            "Confirm your new address",
+            &html_content,
+            &plain_text_content,
+        )
+        .await?;
+    Ok(())
+}
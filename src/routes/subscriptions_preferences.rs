@@ -0,0 +1,379 @@
+use crate::{
+    domain::{SubscriberName, UnsubscribeToken},
+    routes::{invalid_link, unsubscribe_link},
+    startup::{ApplicationBaseUrl, HmacSecretKey},
+    utils::{html_escape, opaque_500_err, see_other},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const INVALID_LINK: &str = "This link is not valid. Please use the link from one of our emails.";
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    name: String,
+    /// `YYYY-MM-DD`, left empty to receive issues right away.
+    paused_until: String,
+}
+
+struct ListMembership {
+    list_id: Uuid,
+    name: String,
+    is_member: bool,
+}
+
+struct Preferences {
+    email: String,
+    name: String,
+    status: String,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+/// Link to the self-service page, included in every issue next to the
+/// unsubscribe link. It is signed with the same per-subscriber token.
+pub fn preferences_link(base_url: &str, subscriber_id: Uuid, secret: &Secret<String>) -> String {
+    let token = UnsubscribeToken::for_subscriber(subscriber_id, secret);
+    format!(
+        "{base_url}/subscriptions/preferences?subscriber_id={subscriber_id}&token={}",
+        token.as_ref()
+    )
+}
+
+#[tracing::instrument(name = "Show subscriber preferences", skip_all)]
+pub async fn preferences_form(
+    params: web::Query<PreferencesParameters>,
+    flash_msg: IncomingFlashMessages,
+    conn_pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecretKey>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if UnsubscribeToken::verify(params.subscriber_id, &params.token, &secret.0).is_err() {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    let Some(preferences) = get_preferences(&conn_pool, params.subscriber_id)
+        .await
+        .map_err(opaque_500_err)?
+    else {
+        return Ok(invalid_link(INVALID_LINK));
+    };
+    let mut html_msg = String::new();
+    for m in flash_msg.iter() {
+        writeln!(html_msg, "<p><i>{}</i></p>", html_escape(m.content())).unwrap();
+    }
+    let status = match preferences.status.as_str() {
+        "unsubscribed" => "You are unsubscribed and won't receive any issues.",
+        "pending_confirmation" => "Your subscription is waiting for confirmation.",
+        _ => "You are subscribed.",
+    };
+    let paused_until = preferences
+        .paused_until
+        .filter(|paused_until| *paused_until > Utc::now())
+        .map(|paused_until| paused_until.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let mut list_checkboxes = String::new();
+    for list in get_list_memberships(&conn_pool, params.subscriber_id)
+        .await
+        .map_err(opaque_500_err)?
+    {
+        writeln!(
+            list_checkboxes,
+            r#"<label><input type="checkbox" name="list" value="{}"{}> {}</label><br>"#,
+            list.list_id,
+            if list.is_member { " checked" } else { "" },
+            html_escape(&list.name)
+        )
+        .unwrap();
+    }
+    let unsubscribe_url = unsubscribe_link(&base_url.0, params.subscriber_id, &secret.0);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Your preferences</title>
+    </head>
+    <body>
+        {html_msg}
+        <p>{status}</p>
+        <form action="/subscriptions/preferences?subscriber_id={subscriber_id}&token={token}" method="post">
+            <label>Name:<br>
+                <input type="text" name="name" value="{name}">
+            </label>
+            <br>
+            <label>Pause issues until (leave empty to receive them right away):<br>
+                <input type="date" name="paused_until" value="{paused_until}">
+            </label>
+            <br>
+            <button type="submit">Save</button>
+        </form>
+        <p>Your address: {email}</p>
+        <form action="/subscriptions/email/request?subscriber_id={subscriber_id}&token={token}" method="post">
+            <button type="submit">Email me a link to change my address</button>
+        </form>
+        <form action="/subscriptions/preferences/lists?subscriber_id={subscriber_id}&token={token}" method="post">
+            <p>Lists you receive:</p>
+            {list_checkboxes}
+            <button type="submit">Save lists</button>
+        </form>
+        <p><a href="{unsubscribe_url}">Unsubscribe from everything</a></p>
+        <form action="/subscriptions/data/request?subscriber_id={subscriber_id}&token={token}" method="post">
+            <button type="submit">Email me links to download or erase my data</button>
+        </form>
+    </body>
+    </html>"#,
+            subscriber_id = params.subscriber_id,
+            token = params.token,
+            name = html_escape(&preferences.name),
+            email = html_escape(&preferences.email),
+        )))
+}
+
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip_all,
+    fields(subscriber_id = %params.subscriber_id)
+)]
+pub async fn update_preferences(
+    params: web::Query<PreferencesParameters>,
+    form: web::Form<PreferencesFormData>,
+    conn_pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecretKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if UnsubscribeToken::verify(params.subscriber_id, &params.token, &secret.0).is_err() {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    let back = format!(
+        "/subscriptions/preferences?subscriber_id={}&token={}",
+        params.subscriber_id, params.token
+    );
+    let PreferencesFormData { name, paused_until } = form.0;
+    let name = match SubscriberName::parse_name(name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+    let paused_until = match parse_paused_until(&paused_until) {
+        Ok(paused_until) => paused_until,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, paused_until = $3
+        WHERE id = $1
+        "#,
+        params.subscriber_id,
+        name.as_ref(),
+        paused_until
+    )
+    .execute(conn_pool.get_ref())
+    .await
+    .context("Failed to update the subscriber's preferences.")
+    .map_err(opaque_500_err)?
+    .rows_affected()
+        > 0;
+    if !updated {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&back))
+}
+
+/// Checkboxes are sent as repeated `list` fields, which `FormData` can't
+/// express.
+#[tracing::instrument(
+    name = "Update subscriber lists",
+    skip_all,
+    fields(subscriber_id = %params.subscriber_id)
+)]
+pub async fn update_list_preferences(
+    params: web::Query<PreferencesParameters>,
+    form: web::Form<Vec<(String, String)>>,
+    conn_pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecretKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if UnsubscribeToken::verify(params.subscriber_id, &params.token, &secret.0).is_err() {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    let back = format!(
+        "/subscriptions/preferences?subscriber_id={}&token={}",
+        params.subscriber_id, params.token
+    );
+    let selected = match form
+        .0
+        .into_iter()
+        .filter(|(key, _)| key == "list")
+        .map(|(_, value)| value.parse::<Uuid>())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(selected) => selected,
+        Err(_) => {
+            FlashMessage::error("Unknown list.").send();
+            return Ok(see_other(&back));
+        }
+    };
+    let Some(preferences) = get_preferences(&conn_pool, params.subscriber_id)
+        .await
+        .map_err(opaque_500_err)?
+    else {
+        return Ok(invalid_link(INVALID_LINK));
+    };
+    set_list_memberships(&conn_pool, params.subscriber_id, &selected)
+        .await
+        .map_err(opaque_500_err)?;
+    if preferences.status == "confirmed" {
+        FlashMessage::info("Your lists have been saved.").send();
+    } else {
+        FlashMessage::info(
+            "Your lists have been saved. Lists can only be joined once your subscription is confirmed.",
+        )
+        .send();
+    }
+    Ok(see_other(&back))
+}
+
+fn parse_paused_until(paused_until: &str) -> Result<Option<DateTime<Utc>>, String> {
+    let paused_until = paused_until.trim();
+    if paused_until.is_empty() {
+        return Ok(None);
+    }
+    let date = NaiveDate::parse_from_str(paused_until, "%Y-%m-%d")
+        .map_err(|_| format!("{paused_until} is not a valid date."))?;
+    Ok(Some(date.and_time(chrono::NaiveTime::MIN).and_utc()))
+}
+
+#[tracing::instrument(name = "Get subscriber preferences", skip(conn_pool))]
+async fn get_preferences(
+    conn_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Preferences>, anyhow::Error> {
+    let preferences = sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT email, name, status, paused_until
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(conn_pool)
+    .await
+    .context("Failed to retrieve the subscriber's preferences.")?;
+    Ok(preferences)
+}
+
+#[tracing::instrument(name = "Get list memberships", skip(conn_pool))]
+async fn get_list_memberships(
+    conn_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListMembership>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            COALESCE(ls.status <> 'unsubscribed', false) AS "is_member!"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls
+            ON ls.list_id = l.list_id AND ls.subscriber_id = $1
+        ORDER BY l.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(conn_pool)
+    .await
+    .context("Failed to retrieve the subscriber's lists.")?;
+    Ok(lists)
+}
+
+/// Following a signed link proves the subscriber owns the address, so
+/// newly ticked lists don't need another confirmation. Subscribers whose
+/// address isn't confirmed can only leave lists: joining one goes through
+/// the subscribe form, which sends the confirmation email.
+#[tracing::instrument(name = "Set list memberships", skip(conn_pool))]
+async fn set_list_memberships(
+    conn_pool: &PgPool,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    let mut transaction = conn_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to leave the unselected lists.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        SELECT l.list_id, s.id, 'confirmed', now()
+        FROM lists l, subscriptions s
+        WHERE s.id = $1 AND s.status = 'confirmed' AND l.list_id = ANY($2)
+        ON CONFLICT (list_id, subscriber_id)
+        DO UPDATE SET status = 'confirmed', subscribed_at = now()
+        WHERE list_subscriptions.status = 'unsubscribed'
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to join the selected lists.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_paused_until;
+    use claim::{assert_err, assert_none, assert_some};
+
+    #[test]
+    fn empty_pause_means_not_paused() {
+        assert_none!(parse_paused_until(" ").unwrap());
+    }
+
+    #[test]
+    fn pause_is_parsed_as_a_date() {
+        let paused_until = assert_some!(parse_paused_until("2024-03-01").unwrap());
+        assert_eq!(paused_until.to_rfc3339(), "2024-03-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        assert_err!(parse_paused_until("2024-02-30"));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    domain::UnsubscribeToken, routes::invalid_link, startup::HmacSecretKey, utils::opaque_500_err,
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const INVALID_LINK: &str =
+    "This unsubscribe link is not valid. Please use the link from one of our emails.";
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+/// The personalised link embedded in every issue and in its
+/// `List-Unsubscribe` header.
+pub fn unsubscribe_link(base_url: &str, subscriber_id: Uuid, secret: &Secret<String>) -> String {
+    let token = UnsubscribeToken::for_subscriber(subscriber_id, secret);
+    format!(
+        "{base_url}/subscriptions/unsubscribe?subscriber_id={subscriber_id}&token={}",
+        token.as_ref()
+    )
+}
+
+/// Asks for a confirmation first, since link scanners and prefetchers follow
+/// every link they find in an email.
+#[tracing::instrument(name = "Show unsubscribe page", skip_all)]
+pub async fn unsubscribe_form(
+    params: web::Query<UnsubscribeParameters>,
+    secret: web::Data<HmacSecretKey>,
+) -> HttpResponse {
+    if UnsubscribeToken::verify(params.subscriber_id, &params.token, &secret.0).is_err() {
+        return invalid_link(INVALID_LINK);
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Unsubscribe</title>
+    </head>
+    <body>
+        <p>Do you really want to stop receiving our newsletter?</p>
+        <form action="/subscriptions/unsubscribe?subscriber_id={}&token={}" method="post">
+            <button type="submit">Unsubscribe</button>
+        </form>
+    </body>
+    </html>"#,
+            params.subscriber_id, params.token
+        ))
+}
+
+/// Also serves RFC 8058 one-click requests, which POST to the
+/// `List-Unsubscribe` URL with a `List-Unsubscribe=One-Click` body.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip_all,
+    fields(subscriber_id = %params.subscriber_id)
+)]
+pub async fn unsubscribe(
+    params: web::Query<UnsubscribeParameters>,
+    conn_pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecretKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if UnsubscribeToken::verify(params.subscriber_id, &params.token, &secret.0).is_err() {
+        return Ok(invalid_link(INVALID_LINK));
+    }
+    mark_as_unsubscribed(&conn_pool, params.subscriber_id)
+        .await
+        .map_err(opaque_500_err)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Unsubscribed</title>
+    </head>
+    <body>
+        <p>You have been unsubscribed and won't receive any further issues.</p>
+    </body>
+    </html>"#,
+    ))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(conn_pool))]
+async fn mark_as_unsubscribed(
+    conn_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = conn_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the subscriber as unsubscribed.")?;
+    // Opting back in later shouldn't silently restore every list.
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to leave the subscriber's lists.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
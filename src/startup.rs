@@ -1,16 +1,26 @@
 use crate::{
     auth::reject_anonymous_users,
-    config::Settings,
+    config::{Settings, WebhookSettings},
     email_client::EmailClient,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, health_check, home, login,
-        login_form, logout, publish_newsletter, subscriptions::subscribe,
+        account_form, add_suppression, admin_dashboard, cancel_issue, change_password,
+        change_password_form, confirm, create_draft, create_layout, create_list, create_segment,
+        dead_letters, delete_draft, delete_layout, download_data, drafts, edit_draft_form,
+        edit_layout_form, email_change_form, email_events, erase, erase_form,
+        erase_subscriber_data, export_subscribers, health_check, home, import_form,
+        import_subscribers, layouts, list_issues, lists, login, login_form, logout, new_draft_form,
+        pause_issue, preferences_form, preview_draft, publish_draft, publish_newsletter,
+        publish_newsletter_form, remove_suppression, request_data_links, request_email_change,
+        requeue_dead_letters, reschedule_issue, resume_issue, segments, send_test_draft,
+        set_default_layout, subscriber_data, subscribers, subscriptions::subscribe, suppressions,
+        unsubscribe, unsubscribe_form, update_account, update_draft, update_email, update_layout,
+        update_list_preferences, update_preferences, update_subscriber,
     },
 };
 //use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{
     cookie::Key,
-    dev::Server,
+    dev::{Server, ServerHandle},
     web::{self, Data},
     App, HttpServer,
 };
@@ -29,6 +39,9 @@ pub struct Application {
 #[derive(Debug)]
 pub struct ApplicationBaseUrl(pub String);
 
+#[derive(Debug)]
+pub struct PostalAddress(pub String);
+
 #[derive(Clone, Debug)]
 pub struct HmacSecretKey(pub Secret<String>);
 
@@ -38,18 +51,7 @@ impl Application {
             .acquire_timeout(std::time::Duration::from_secs(2))
             .connect_lazy_with(config.database.get_db_options());
 
-        let sender_email = config
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-
-        let timeout = config.email_client.timeout();
-        let email_client = EmailClient::new(
-            config.email_client.base_url,
-            sender_email,
-            config.email_client.authorization_token,
-            timeout,
-        );
+        let email_client = config.email_client.client();
 
         let address = format!("{}:{}", config.app_settings.host, config.app_settings.port);
 
@@ -61,6 +63,8 @@ impl Application {
             email_client,
             config.app_settings.base_url,
             config.app_settings.hmac_secret,
+            config.app_settings.postal_address,
+            config.webhooks,
             //config.redis_uri,
         )
         .await?;
@@ -74,6 +78,12 @@ impl Application {
     pub fn port(&self) -> u16 {
         self.port
     }
+
+    /// Used to stop the server gracefully, since it doesn't listen for
+    /// signals itself.
+    pub fn server_handle(&self) -> ServerHandle {
+        self.server.handle()
+    }
 }
 
 async fn run(
@@ -82,10 +92,15 @@ async fn run(
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
+    postal_address: String,
+    webhooks: Option<WebhookSettings>,
     //redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
+    let webhooks = Data::new(webhooks);
     let conn_pool = web::Data::new(conn_pool);
     let email_client = web::Data::new(email_client);
+    let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let postal_address = Data::new(PostalAddress(postal_address));
     let key = Key::from(hmac_secret.expose_secret().as_bytes());
     let msg_store = CookieMessageStore::builder(key.clone()).build();
     let msg_framework = FlashMessagesFramework::builder(msg_store).build();
@@ -99,22 +114,101 @@ async fn run(
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
-            .route("/newsletter", web::post().to(publish_newsletter)) // Add the missing function here
-            .route("/subsrciptions", web::post().to(subscribe))
+            .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route(
+                "/subscriptions/email/request",
+                web::post().to(request_email_change),
+            )
+            .route("/subscriptions/email", web::get().to(email_change_form))
+            .route("/subscriptions/email", web::post().to(update_email))
+            .route(
+                "/subscriptions/preferences/lists",
+                web::post().to(update_list_preferences),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/data", web::get().to(download_data))
+            .route(
+                "/subscriptions/data/request",
+                web::post().to(request_data_links),
+            )
+            .route("/subscriptions/erase", web::get().to(erase_form))
+            .route("/subscriptions/erase", web::post().to(erase))
+            .route("/webhooks/email-events", web::post().to(email_events))
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(logout)),
+                    .route("/account", web::get().to(account_form))
+                    .route("/account", web::post().to(update_account))
+                    .route("/logout", web::post().to(logout))
+                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/drafts", web::get().to(drafts))
+                    .route("/drafts", web::post().to(create_draft))
+                    .route("/drafts/new", web::get().to(new_draft_form))
+                    .route("/drafts/{issue_id}", web::get().to(edit_draft_form))
+                    .route("/drafts/{issue_id}", web::post().to(update_draft))
+                    .route("/drafts/{issue_id}/preview", web::get().to(preview_draft))
+                    .route("/drafts/{issue_id}/test", web::post().to(send_test_draft))
+                    .route("/drafts/{issue_id}/publish", web::post().to(publish_draft))
+                    .route("/drafts/{issue_id}/delete", web::post().to(delete_draft))
+                    .route("/dead_letters", web::get().to(dead_letters))
+                    .route("/dead_letters", web::post().to(requeue_dead_letters))
+                    .route("/subscribers", web::get().to(subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route("/subscribers/import", web::get().to(import_form))
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route("/subscribers/data", web::get().to(subscriber_data))
+                    .route("/subscribers/erase", web::post().to(erase_subscriber_data))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::post().to(update_subscriber),
+                    )
+                    .route("/suppressions", web::get().to(suppressions))
+                    .route("/suppressions", web::post().to(add_suppression))
+                    .route("/suppressions/remove", web::post().to(remove_suppression))
+                    .route("/segments", web::get().to(segments))
+                    .route("/segments", web::post().to(create_segment))
+                    .route("/layouts", web::get().to(layouts))
+                    .route("/layouts", web::post().to(create_layout))
+                    .route("/layouts/default", web::post().to(set_default_layout))
+                    .route("/layouts/{layout_id}", web::get().to(edit_layout_form))
+                    .route("/layouts/{layout_id}", web::post().to(update_layout))
+                    .route("/layouts/{layout_id}/delete", web::post().to(delete_layout))
+                    .route("/lists", web::get().to(lists))
+                    .route("/lists", web::post().to(create_list))
+                    .route("/issues", web::get().to(list_issues))
+                    .route("/issues/{issue_id}/pause", web::post().to(pause_issue))
+                    .route("/issues/{issue_id}/resume", web::post().to(resume_issue))
+                    .route("/issues/{issue_id}/cancel", web::post().to(cancel_issue))
+                    .route(
+                        "/issues/{issue_id}/reschedule",
+                        web::post().to(reschedule_issue),
+                    ),
             )
             .app_data(email_client.clone())
             .app_data(conn_pool.clone())
             .app_data(base_url.clone())
+            .app_data(postal_address.clone())
             .app_data(Data::new(HmacSecretKey(hmac_secret.clone())))
+            .app_data(webhooks.clone())
     })
+    .disable_signals()
     .listen(listener)?
     .run();
     Ok(server)
@@ -0,0 +1,502 @@
+//! Bulk loading of subscribers from CSV files, shared by the admin upload
+//! and the `import_subscribers` binary.
+use crate::{
+    confirmation_queue::enqueue_confirmation_email,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    routes::{error_chain_fmt, generate_subscription_token, store_token},
+    suppression::suppression_reason,
+};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Rows are parsed as soon as they are complete, so only an unfinished row
+/// is ever buffered. Anything longer than this is not a subscriber.
+const MAX_ROW_BYTES: usize = 1024 * 1024;
+
+pub struct ImportOptions {
+    pub list_id: Uuid,
+    /// Skip the confirmation email, e.g. for addresses that already
+    /// confirmed with a previous provider.
+    pub pre_confirmed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowOutcome {
+    Accepted,
+    Skipped,
+    Rejected,
+}
+
+pub struct RowReport {
+    /// Line of the file the row starts on, counting the header as line 1.
+    pub line: u64,
+    pub email: String,
+    pub outcome: RowOutcome,
+    pub reason: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Feed it the file with `push`, in chunks of any size, then call `finish`
+/// and `into_report` to get the per-row report. Each row is committed on its
+/// own, so an interrupted import can simply be run again, and the report
+/// still covers the rows imported before the interruption. Confirmation
+/// emails are queued for the background worker.
+pub struct SubscriberImport<'a> {
+    pool: &'a PgPool,
+    options: ImportOptions,
+    rows: CsvRows,
+    /// Line each address was first seen on.
+    seen: HashMap<String, u64>,
+    report: Vec<RowReport>,
+}
+
+impl<'a> SubscriberImport<'a> {
+    pub fn new(pool: &'a PgPool, options: ImportOptions) -> Self {
+        Self {
+            pool,
+            options,
+            rows: CsvRows::default(),
+            seen: HashMap::new(),
+            report: vec![],
+        }
+    }
+
+    pub async fn push(&mut self, chunk: &[u8]) -> Result<(), ImportError> {
+        let rows = self.rows.push(chunk).map_err(ImportError::InvalidFile)?;
+        for row in rows {
+            self.import_row(row).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn finish(&mut self) -> Result<(), ImportError> {
+        let rows = std::mem::take(&mut self.rows)
+            .finish()
+            .map_err(ImportError::InvalidFile)?;
+        for row in rows {
+            self.import_row(row).await?;
+        }
+        Ok(())
+    }
+
+    /// The rows imported so far.
+    pub fn into_report(self) -> Vec<RowReport> {
+        self.report
+    }
+
+    async fn import_row(&mut self, row: CsvRow) -> Result<(), anyhow::Error> {
+        let line = row.line;
+        let (email, name) = match row.fields {
+            Ok(fields) => fields,
+            Err(reason) => {
+                self.record(line, String::new(), RowOutcome::Rejected, reason);
+                return Ok(());
+            }
+        };
+        let new_subscriber = match SubscriberEmail::parse_email(email.clone()).and_then(|email| {
+            let name = SubscriberName::parse_name(name)?;
+            Ok(NewSubscriber { email, name })
+        }) {
+            Ok(new_subscriber) => new_subscriber,
+            Err(reason) => {
+                self.record(line, email, RowOutcome::Rejected, reason);
+                return Ok(());
+            }
+        };
+        if let Some(first_line) = self.seen.get(&email.to_lowercase()) {
+            let reason = format!("Duplicate of line {first_line}.");
+            self.record(line, email, RowOutcome::Skipped, reason);
+            return Ok(());
+        }
+        self.seen.insert(email.to_lowercase(), line);
+
+        let (outcome, reason) = self.upsert(&new_subscriber).await?;
+        self.record(line, email, outcome, reason);
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Import a subscriber", skip_all)]
+    async fn upsert(
+        &self,
+        new_subscriber: &NewSubscriber,
+    ) -> Result<(RowOutcome, String), anyhow::Error> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?;
+        if let Some(reason) = suppression_reason(&mut *transaction, new_subscriber.email.as_ref())
+            .await
+            .context("Failed to check the suppression list.")?
+        {
+            let reason = format!("The address is suppressed ({reason}), it was not added back.");
+            return Ok((RowOutcome::Skipped, reason));
+        }
+        let (subscriber_id, existing) = upsert_subscriber(&mut transaction, new_subscriber)
+            .await
+            .context("Failed to upsert the subscriber.")?;
+        let list_status = match existing {
+            Some(_) => get_list_status(&mut transaction, self.options.list_id, subscriber_id)
+                .await
+                .context("Failed to retrieve the list membership.")?,
+            None => None,
+        };
+        let action = row_action(
+            existing.as_deref(),
+            list_status.as_deref(),
+            self.options.pre_confirmed,
+        );
+        let reason = match action {
+            RowAction::Skip(reason) => return Ok((RowOutcome::Skipped, reason.into())),
+            RowAction::KeepMembership => "Already subscribed, details updated.",
+            RowAction::Confirm => {
+                confirm_membership(&mut transaction, self.options.list_id, subscriber_id)
+                    .await
+                    .context("Failed to confirm the list membership.")?;
+                "Added as confirmed."
+            }
+            RowAction::SendConfirmation => {
+                set_membership(
+                    &mut transaction,
+                    self.options.list_id,
+                    subscriber_id,
+                    "pending_confirmation",
+                )
+                .await
+                .context("Failed to add the subscriber to the list.")?;
+                let token = generate_subscription_token();
+                store_token(&mut transaction, subscriber_id, &token, None)
+                    .await
+                    .context("Failed to store subscription token in the database.")?;
+                enqueue_confirmation_email(&mut transaction, &token)
+                    .await
+                    .context("Failed to queue the confirmation email.")?;
+                "Added, a confirmation email will be sent."
+            }
+        };
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction.")?;
+        Ok((RowOutcome::Accepted, reason.into()))
+    }
+
+    fn record(&mut self, line: u64, email: String, outcome: RowOutcome, reason: String) {
+        self.report.push(RowReport {
+            line,
+            email,
+            outcome,
+            reason,
+        });
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum RowAction {
+    Skip(&'static str),
+    KeepMembership,
+    Confirm,
+    SendConfirmation,
+}
+
+/// `status` is `None` for addresses the import just created.
+fn row_action(status: Option<&str>, list_status: Option<&str>, pre_confirmed: bool) -> RowAction {
+    // An import must never undo an opt-out.
+    if status == Some("unsubscribed") {
+        return RowAction::Skip("The address unsubscribed, it was not added back.");
+    }
+    match list_status {
+        Some("unsubscribed") => {
+            RowAction::Skip("The address unsubscribed from this list, it was not added back.")
+        }
+        Some("confirmed") => RowAction::KeepMembership,
+        _ if pre_confirmed => RowAction::Confirm,
+        _ => RowAction::SendConfirmation,
+    }
+}
+
+/// Returns the subscriber id, and their status if they already existed
+/// (in which case their name is updated).
+async fn upsert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<(Uuid, Option<String>), sqlx::Error> {
+    let inserted = sqlx::query_scalar!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, now(), 'pending_confirmation')
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    if let Some(subscriber_id) = inserted {
+        return Ok((subscriber_id, None));
+    }
+    let existing = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = CASE WHEN status = 'unsubscribed' THEN name ELSE $2 END
+        WHERE email = $1
+        RETURNING id, status
+        "#,
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref()
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok((existing.id, Some(existing.status)))
+}
+
+async fn get_list_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT status FROM list_subscriptions
+        WHERE list_id = $1 AND subscriber_id = $2
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+async fn set_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (list_id, subscriber_id)
+        DO UPDATE SET status = $3, subscribed_at = now()
+        "#,
+        list_id,
+        subscriber_id,
+        status
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Pre-confirmed rows also confirm the address itself.
+async fn confirm_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    set_membership(transaction, list_id, subscriber_id, "confirmed").await
+}
+
+#[derive(Debug)]
+struct CsvRow {
+    line: u64,
+    /// Email and name, or why they couldn't be read.
+    fields: Result<(String, String), String>,
+}
+
+/// Incremental CSV reader: buffers bytes until a line break outside of
+/// quotes, then parses everything before it.
+#[derive(Default)]
+struct CsvRows {
+    buffer: Vec<u8>,
+    /// How much of `buffer` was already scanned for line breaks.
+    scanned: usize,
+    in_quotes: bool,
+    /// Lines parsed so far.
+    n_lines: u64,
+    /// Positions of the email and name columns, once the header is read.
+    columns: Option<(usize, usize)>,
+}
+
+impl CsvRows {
+    fn push(&mut self, chunk: &[u8]) -> Result<Vec<CsvRow>, String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut complete = None;
+        for (i, byte) in self.buffer.iter().enumerate().skip(self.scanned) {
+            match byte {
+                b'"' => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => complete = Some(i + 1),
+                _ => {}
+            }
+        }
+        self.scanned = self.buffer.len();
+        let Some(complete) = complete else {
+            if self.buffer.len() > MAX_ROW_BYTES {
+                return Err("The file contains a row longer than 1 MiB.".into());
+            }
+            return Ok(vec![]);
+        };
+        let data: Vec<u8> = self.buffer.drain(..complete).collect();
+        self.scanned -= complete;
+        self.parse(&data)
+    }
+
+    fn finish(mut self) -> Result<Vec<CsvRow>, String> {
+        let data = std::mem::take(&mut self.buffer);
+        let rows = self.parse(&data)?;
+        if self.columns.is_none() {
+            return Err("The file is empty.".into());
+        }
+        Ok(rows)
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Vec<CsvRow>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(data);
+        let mut record = csv::StringRecord::new();
+        let mut rows = vec![];
+        let mut counted = 0;
+        let count_lines = |bytes: &[u8]| bytes.iter().filter(|byte| **byte == b'\n').count() as u64;
+        loop {
+            let read = reader.read_record(&mut record);
+            let position = match &read {
+                Ok(_) => record.position(),
+                Err(e) => e.position(),
+            };
+            // The reported line isn't reliable after `\r\n` line breaks, so
+            // count them from the record's offset instead.
+            let mut start = position.map_or(counted, |p| p.byte() as usize);
+            while matches!(data.get(start), Some(b'\r' | b'\n')) {
+                start += 1;
+            }
+            self.n_lines += count_lines(&data[counted..start]);
+            counted = start;
+            let line = self.n_lines + 1;
+            match read {
+                Ok(false) => break,
+                Ok(true) => {}
+                Err(_) => {
+                    rows.push(CsvRow {
+                        line,
+                        fields: Err("The row is not valid UTF-8.".into()),
+                    });
+                    continue;
+                }
+            }
+            let Some((email, name)) = self.columns else {
+                self.columns = Some(find_columns(&record)?);
+                continue;
+            };
+            let field = |i| record.get(i).unwrap_or_default().trim().to_owned();
+            rows.push(CsvRow {
+                line,
+                fields: Ok((field(email), field(name))),
+            });
+        }
+        self.n_lines += count_lines(&data[counted..]);
+        Ok(rows)
+    }
+}
+
+fn find_columns(header: &csv::StringRecord) -> Result<(usize, usize), String> {
+    let find = |column: &str| {
+        header
+            .iter()
+            .position(|h| {
+                h.trim_start_matches('\u{feff}')
+                    .trim()
+                    .eq_ignore_ascii_case(column)
+            })
+            .ok_or_else(|| format!("The header row has no `{column}` column."))
+    };
+    Ok((find("email")?, find("name")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{row_action, CsvRows, RowAction};
+    use claim::assert_err;
+
+    fn read_in_chunks(data: &str, chunk_size: usize) -> Vec<(u64, String, String)> {
+        let mut rows = CsvRows::default();
+        let mut parsed = vec![];
+        for chunk in data.as_bytes().chunks(chunk_size) {
+            parsed.extend(rows.push(chunk).unwrap());
+        }
+        parsed.extend(rows.finish().unwrap());
+        parsed
+            .into_iter()
+            .map(|row| {
+                let (email, name) = row.fields.unwrap();
+                (row.line, email, name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rows_are_the_same_whatever_the_chunk_size() {
+        let data = "\u{feff}Name,Email\r\n\"Le Guin, Ursula\",ursula@example.com\n\"multi\nline\",m@example.com\nada,ada@example.com";
+        let expected = vec![
+            (2, "ursula@example.com".into(), "Le Guin, Ursula".into()),
+            (3, "m@example.com".into(), "multi\nline".into()),
+            (5, "ada@example.com".into(), "ada".into()),
+        ];
+        for chunk_size in [1, 3, 7, data.len()] {
+            assert_eq!(read_in_chunks(data, chunk_size), expected);
+        }
+    }
+
+    #[test]
+    fn header_without_an_email_column_is_rejected() {
+        let mut rows = CsvRows::default();
+        assert_err!(rows.push(b"name,address\n"));
+    }
+
+    #[test]
+    fn unsubscribed_addresses_are_skipped() {
+        assert!(matches!(
+            row_action(Some("unsubscribed"), None, true),
+            RowAction::Skip(_)
+        ));
+        assert!(matches!(
+            row_action(Some("confirmed"), Some("unsubscribed"), true),
+            RowAction::Skip(_)
+        ));
+    }
+
+    #[test]
+    fn new_rows_are_confirmed_only_when_asked_to() {
+        assert_eq!(row_action(None, None, true), RowAction::Confirm);
+        assert_eq!(row_action(None, None, false), RowAction::SendConfirmation);
+        assert_eq!(
+            row_action(Some("confirmed"), Some("confirmed"), false),
+            RowAction::KeepMembership
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Addresses that must never be emailed again, whatever the status of
+//! their subscription.
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, Postgres, Transaction};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuppressionReason {
+    HardBounce,
+    SpamComplaint,
+    /// Added by an admin.
+    Manual,
+    /// The subscriber asked for all their data to be erased.
+    Erased,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::HardBounce => "hard_bounce",
+            SuppressionReason::SpamComplaint => "spam_complaint",
+            SuppressionReason::Manual => "manual",
+            SuppressionReason::Erased => "erased",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "hard_bounce" => Ok(SuppressionReason::HardBounce),
+            "spam_complaint" => Ok(SuppressionReason::SpamComplaint),
+            "manual" => Ok(SuppressionReason::Manual),
+            "erased" => Ok(SuppressionReason::Erased),
+            _ => Err(format!("{s} is not a suppression reason.")),
+        }
+    }
+}
+
+/// Suppression entries are keyed by this rather than by the address itself.
+/// The `hash_email` SQL function computes the same thing.
+pub fn email_hash(email: &str) -> String {
+    hex::encode(Sha256::digest(email.trim().to_lowercase()))
+}
+
+/// Erased entries are never overwritten, so that a later bounce or manual
+/// suppression can't store the address again.
+#[tracing::instrument(name = "Suppress an address", skip(transaction, email))]
+pub async fn suppress(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: SuppressionReason,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, reason, email)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (email_hash) DO UPDATE SET reason = EXCLUDED.reason, email = EXCLUDED.email
+        WHERE suppressions.reason <> 'erased'
+        "#,
+        email_hash(email),
+        reason.as_str(),
+        // Erasure must not leave the address behind.
+        (reason != SuppressionReason::Erased).then_some(email)
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Returns why the address is suppressed, if it is.
+pub async fn suppression_reason(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT reason FROM suppressions WHERE email_hash = $1",
+        email_hash(email)
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Lets the address be emailed again. Erased addresses stay suppressed.
+#[tracing::instrument(name = "Remove a suppression", skip(executor))]
+pub async fn unsuppress(
+    executor: impl PgExecutor<'_>,
+    email_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted = sqlx::query!(
+        "DELETE FROM suppressions WHERE email_hash = $1 AND reason <> 'erased'",
+        email_hash
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_deleted == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{email_hash, SuppressionReason};
+
+    #[test]
+    fn hashes_ignore_case_and_surrounding_whitespace() {
+        assert_eq!(
+            email_hash(" Ursula@Example.com "),
+            email_hash("ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn reasons_round_trip() {
+        for reason in [
+            SuppressionReason::HardBounce,
+            SuppressionReason::SpamComplaint,
+            SuppressionReason::Manual,
+            SuppressionReason::Erased,
+        ] {
+            assert_eq!(SuppressionReason::parse(reason.as_str()), Ok(reason));
+        }
+    }
+
+    #[test]
+    fn hashes_do_not_contain_the_address() {
+        let hash = email_hash("ursula@example.com");
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains("ursula"));
+    }
+}